@@ -0,0 +1,692 @@
+//! Command-line tool to create, fund and administer reward pools.
+
+mod rpc;
+
+use anchor_lang::Discriminator;
+use clap::{ArgEnum, Parser, Subcommand};
+use reward_pool_client::reward_pool::{self, PenaltyCurve, PenaltyDestination, RewardLockPolicy, BPS_DENOMINATOR, PAUSE_ALL};
+use reward_pool_client::{Pool, PoolConfig, User};
+use serde_json::{json, Value};
+use solana_sdk::instruction::Instruction;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::Transaction;
+use std::path::PathBuf;
+
+use rpc::RpcClient;
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+#[derive(Parser)]
+#[clap(name = "reward-pool", about = "Create, fund and administer reward pools")]
+struct Cli {
+    /// RPC URL or one of mainnet, devnet, testnet and localnet.
+    #[clap(long, short = 'u', global = true, default_value = "localnet")]
+    url: String,
+    /// Keypair paying for and signing transactions [default: ~/.config/solana/id.json].
+    #[clap(long, short = 'k', global = true)]
+    keypair: Option<PathBuf>,
+    /// Simulate transactions instead of sending them.
+    #[clap(long, global = true)]
+    dry_run: bool,
+    /// Print results as JSON.
+    #[clap(long, global = true)]
+    json: bool,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a pool along with its staking and reward vaults.
+    CreatePool(CreatePoolArgs),
+    /// Fund a reward stream from a token account of the signer.
+    Fund {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long, default_value = "0")]
+        stream_index: u8,
+        #[clap(long)]
+        amount: u64,
+        /// Token account the rewards are transferred from.
+        #[clap(long)]
+        from: Pubkey,
+    },
+    /// Pause operations, all of them unless flags are given.
+    Pause {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long, default_value_t = PAUSE_ALL)]
+        flags: u8,
+    },
+    /// Resume operations, all of them unless flags are given.
+    Unpause {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long, default_value_t = PAUSE_ALL)]
+        flags: u8,
+    },
+    /// Allow a key to fund the pool's reward streams.
+    AuthorizeFunder {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long)]
+        funder: Pubkey,
+    },
+    /// Revoke a funder.
+    DeauthorizeFunder {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long)]
+        funder: Pubkey,
+    },
+    /// Create a receipt mint and have staking mint receipts from it. The pool must
+    /// be empty and without lock periods.
+    EnableReceipts {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Close a paused pool with no users, refunding its vaults.
+    ClosePool {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Receives the lamports of the closed accounts [default: signer].
+        #[clap(long)]
+        refundee: Option<Pubkey>,
+        #[clap(long)]
+        staking_refundee: Pubkey,
+        #[clap(long)]
+        reward_refundee: Pubkey,
+        /// Reward refundee of each other active stream, in stream order.
+        #[clap(long)]
+        extra_reward_refundee: Vec<Pubkey>,
+    },
+    /// Show a pool's state.
+    InspectPool {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Show a user's stake and pending rewards.
+    InspectUser {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long)]
+        owner: Pubkey,
+    },
+    /// List the users of a pool with their stake and pending rewards.
+    ListUsers {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+}
+
+#[derive(clap::Args)]
+struct CreatePoolArgs {
+    #[clap(long)]
+    staking_mint: Pubkey,
+    #[clap(long)]
+    reward_mint: Pubkey,
+    /// Token account of the staking mint receiving treasury penalties.
+    #[clap(long)]
+    treasury: Pubkey,
+    #[clap(long)]
+    reward_duration: u64,
+    #[clap(long)]
+    lock_period: u64,
+    /// Longest lock a deposit may choose [default: lock period].
+    #[clap(long)]
+    max_lock_period: Option<u64>,
+    #[clap(long, default_value_t = BPS_DENOMINATOR)]
+    max_lock_multiplier_bps: u64,
+    #[clap(long, arg_enum, default_value = "disabled")]
+    penalty_curve: PenaltyCurveArg,
+    #[clap(long, default_value = "0")]
+    early_unstake_penalty_bps: u16,
+    #[clap(long, arg_enum, default_value = "treasury")]
+    penalty_destination: PenaltyDestinationArg,
+    #[clap(long, arg_enum, default_value = "with-principal")]
+    reward_lock_policy: RewardLockPolicyArg,
+    #[clap(long, default_value = "0")]
+    reward_lock_period: u64,
+    /// Stop compounding from resetting the position's lock, compounding then waits
+    /// for the claim lock instead.
+    #[clap(long)]
+    no_compound_lock_reset: bool,
+    #[clap(long, default_value = "0")]
+    vesting_period: u64,
+    #[clap(long, default_value = "0")]
+    vesting_cliff: u64,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum PenaltyCurveArg {
+    Disabled,
+    Flat,
+    LinearDecay,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum PenaltyDestinationArg {
+    Stakers,
+    Treasury,
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum RewardLockPolicyArg {
+    WithPrincipal,
+    Anytime,
+    Independent,
+}
+
+impl CreatePoolArgs {
+    fn config(&self) -> PoolConfig {
+        PoolConfig {
+            max_lock_period: self.max_lock_period.unwrap_or(self.lock_period),
+            max_lock_multiplier_bps: self.max_lock_multiplier_bps,
+            penalty_curve: match self.penalty_curve {
+                PenaltyCurveArg::Disabled => PenaltyCurve::Disabled,
+                PenaltyCurveArg::Flat => PenaltyCurve::Flat,
+                PenaltyCurveArg::LinearDecay => PenaltyCurve::LinearDecay,
+            },
+            early_unstake_penalty_bps: self.early_unstake_penalty_bps,
+            penalty_destination: match self.penalty_destination {
+                PenaltyDestinationArg::Stakers => PenaltyDestination::Stakers,
+                PenaltyDestinationArg::Treasury => PenaltyDestination::Treasury,
+            },
+            reward_lock_policy: match self.reward_lock_policy {
+                RewardLockPolicyArg::WithPrincipal => RewardLockPolicy::WithPrincipal,
+                RewardLockPolicyArg::Anytime => RewardLockPolicy::Anytime,
+                RewardLockPolicyArg::Independent => RewardLockPolicy::Independent,
+            },
+            reward_lock_period: self.reward_lock_period,
+            compound_resets_lock: !self.no_compound_lock_reset,
+            vesting_period: self.vesting_period,
+            vesting_cliff: self.vesting_cliff,
+        }
+    }
+}
+
+impl Command {
+    fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Command::InspectPool { .. } | Command::InspectUser { .. } | Command::ListUsers { .. }
+        )
+    }
+}
+
+struct Context {
+    rpc: RpcClient,
+    /// Not loaded for read-only commands.
+    payer: Option<Keypair>,
+    dry_run: bool,
+    json: bool,
+}
+
+impl Context {
+    fn payer(&self) -> Result<&Keypair> {
+        self.payer.as_ref().ok_or_else(|| "no keypair loaded".into())
+    }
+
+    /// Sends or, on a dry run, simulates the instructions, returning the outcome as JSON.
+    fn process(&self, instructions: &[Instruction], extra_signers: &[&Keypair]) -> Result<Value> {
+        let payer = self.payer()?;
+        let mut signers: Vec<&dyn Signer> = vec![payer];
+        signers.extend(extra_signers.iter().map(|x| *x as &dyn Signer));
+
+        let blockhash = self.rpc.get_latest_blockhash()?;
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&payer.pubkey()),
+            &signers,
+            blockhash,
+        );
+
+        if self.dry_run {
+            let (err, logs) = self.rpc.simulate_transaction(&transaction)?;
+            return Ok(json!({"simulation": {"err": err, "logs": logs}}));
+        }
+        let signature = self.rpc.send_and_confirm_transaction(&transaction)?;
+        Ok(json!({"signature": signature.to_string()}))
+    }
+
+    fn fetch_pool(&self, pool: &Pubkey) -> Result<Pool> {
+        let data = self.rpc.get_account_data(pool)?.ok_or("pool account not found")?;
+        Ok(reward_pool_client::deserialize_pool(&data)?)
+    }
+
+    fn print(&self, value: &Value) -> Result<()> {
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(value)?);
+        } else {
+            print_value(value, 0);
+        }
+        Ok(())
+    }
+}
+
+fn print_value(value: &Value, indent: usize) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    Value::Object(_) | Value::Array(_) => {
+                        println!("{:indent$}{}:", "", key, indent = indent);
+                        print_value(value, indent + 2);
+                    }
+                    _ => println!("{:indent$}{}: {}", "", key, display(value), indent = indent),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                match item {
+                    Value::Object(_) | Value::Array(_) => {
+                        println!("{:indent$}-", "", indent = indent);
+                        print_value(item, indent + 2);
+                    }
+                    _ => println!("{:indent$}- {}", "", display(item), indent = indent),
+                }
+            }
+        }
+        _ => println!("{:indent$}{}", "", display(value), indent = indent),
+    }
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+fn cluster_url(url: &str) -> String {
+    match url {
+        "mainnet" | "m" => "https://api.mainnet-beta.solana.com",
+        "devnet" | "d" => "https://api.devnet.solana.com",
+        "testnet" | "t" => "https://api.testnet.solana.com",
+        "localnet" | "l" => "http://127.0.0.1:8899",
+        url => url,
+    }
+    .to_string()
+}
+
+fn default_keypair_path() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    PathBuf::from(home).join(".config/solana/id.json")
+}
+
+/// Names matching the `create-pool` argument values.
+fn penalty_curve_name(curve: PenaltyCurve) -> &'static str {
+    match curve {
+        PenaltyCurve::Disabled => "disabled",
+        PenaltyCurve::Flat => "flat",
+        PenaltyCurve::LinearDecay => "linear-decay",
+    }
+}
+
+fn penalty_destination_name(destination: PenaltyDestination) -> &'static str {
+    match destination {
+        PenaltyDestination::Stakers => "stakers",
+        PenaltyDestination::Treasury => "treasury",
+    }
+}
+
+fn reward_lock_policy_name(policy: RewardLockPolicy) -> &'static str {
+    match policy {
+        RewardLockPolicy::WithPrincipal => "with-principal",
+        RewardLockPolicy::Anytime => "anytime",
+        RewardLockPolicy::Independent => "independent",
+    }
+}
+
+fn pool_json(pool_key: &Pubkey, pool: &Pool) -> Value {
+    let streams: Vec<Value> = pool
+        .reward_streams
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.is_active())
+        .map(|(i, x)| {
+            json!({
+                "index": i,
+                "mint": x.mint.to_string(),
+                "vault": x.vault.to_string(),
+                "reward_start_time": x.reward_start_time,
+                "reward_duration_end": x.reward_duration_end,
+                "last_update_time": x.last_update_time,
+                "reward_rate": x.reward_rate.to_string(),
+                "reward_per_token_stored": x.reward_per_token_stored.to_string(),
+                "reward_liability": x.reward_liability,
+                "vesting_outstanding": x.vesting_outstanding,
+                "undistributed": x.undistributed,
+            })
+        })
+        .collect();
+    let funders: Vec<String> = pool
+        .funders
+        .iter()
+        .filter(|x| **x != Pubkey::default())
+        .map(|x| x.to_string())
+        .collect();
+
+    json!({
+        "pool": pool_key.to_string(),
+        "pool_signer": reward_pool_client::pool_signer_address(pool_key).0.to_string(),
+        "authority": pool.authority.to_string(),
+        "pending_authority": pool.pending_authority.to_string(),
+        "guardian": pool.guardian.to_string(),
+        "pause_flags": pool.pause_flags,
+        "emergency": pool.emergency,
+        "staking_mint": pool.staking_mint.to_string(),
+        "staking_vault": pool.staking_vault.to_string(),
+        "treasury": pool.treasury.to_string(),
+        "reward_duration": pool.reward_duration,
+        "lock_period": pool.lock_period,
+        "max_lock_period": pool.max_lock_period,
+        "max_lock_multiplier_bps": pool.max_lock_multiplier_bps,
+        "penalty_curve": penalty_curve_name(pool.penalty_curve),
+        "early_unstake_penalty_bps": pool.early_unstake_penalty_bps,
+        "penalty_destination": penalty_destination_name(pool.penalty_destination),
+        "reward_lock_policy": reward_lock_policy_name(pool.reward_lock_policy),
+        "reward_lock_period": pool.reward_lock_period,
+        "reward_lock_start": pool.reward_lock_start,
+        "compound_resets_lock": pool.compound_resets_lock,
+        "vesting_period": pool.vesting_period,
+        "vesting_cliff": pool.vesting_cliff,
+        "user_stake_count": pool.user_stake_count,
+        "total_staked": pool.total_staked,
+        "total_weighted_stake": pool.total_weighted_stake,
+        "receipt_mint": pool.receipt_mint.to_string(),
+        "funders": funders,
+        "reward_streams": streams,
+    })
+}
+
+fn user_json(user_key: &Pubkey, user: &User, pending: &[u64]) -> Value {
+    let positions: Vec<Value> = user
+        .positions
+        .iter()
+        .filter(|x| x.amount > 0)
+        .map(|x| {
+            json!({
+                "amount": x.amount,
+                "weight": x.weight,
+                "start_time": x.start_time,
+                "unlock_time": x.unlock_time,
+            })
+        })
+        .collect();
+
+    json!({
+        "user": user_key.to_string(),
+        "owner": user.owner.to_string(),
+        "balance_staked": user.balance_staked,
+        "balance_weighted": user.balance_weighted,
+        "reward_unlock_time": user.reward_unlock_time,
+        "pending_rewards": pending,
+        "positions": positions,
+    })
+}
+
+/// Creates a token account owned by the pool signer, as the pool requires of its vaults.
+fn create_vault(ctx: &Context, vault: &Keypair, mint: &Pubkey, pool_signer: &Pubkey, lamports: u64) -> Result<Vec<Instruction>> {
+    Ok(vec![
+        system_instruction::create_account(
+            &ctx.payer()?.pubkey(),
+            &vault.pubkey(),
+            lamports,
+            spl_token::state::Account::LEN as u64,
+            &spl_token::ID,
+        ),
+        spl_token::instruction::initialize_account(&spl_token::ID, &vault.pubkey(), mint, pool_signer)?,
+    ])
+}
+
+fn create_pool(ctx: &Context, args: &CreatePoolArgs) -> Result<Value> {
+    let pool = Keypair::new();
+    let staking_vault = Keypair::new();
+    let reward_vault = Keypair::new();
+    let (pool_signer, _) = reward_pool_client::pool_signer_address(&pool.pubkey());
+
+    let pool_lamports = ctx.rpc.get_minimum_balance_for_rent_exemption(reward_pool_client::pool_account_size())?;
+    let vault_lamports = ctx.rpc.get_minimum_balance_for_rent_exemption(spl_token::state::Account::LEN)?;
+
+    let mut instructions = vec![reward_pool_client::create_pool_account(
+        &ctx.payer()?.pubkey(),
+        &pool.pubkey(),
+        pool_lamports,
+    )];
+    instructions.extend(create_vault(ctx, &staking_vault, &args.staking_mint, &pool_signer, vault_lamports)?);
+    instructions.extend(create_vault(ctx, &reward_vault, &args.reward_mint, &pool_signer, vault_lamports)?);
+    instructions.push(reward_pool_client::initialize_pool(
+        &reward_pool_client::InitializePoolAccounts {
+            pool: pool.pubkey(),
+            authority: ctx.payer()?.pubkey(),
+            staking_mint: args.staking_mint,
+            staking_vault: staking_vault.pubkey(),
+            reward_mint: args.reward_mint,
+            reward_vault: reward_vault.pubkey(),
+            treasury: args.treasury,
+        },
+        args.reward_duration,
+        args.lock_period,
+        args.config(),
+    ));
+
+    let mut result = ctx.process(&instructions, &[&pool, &staking_vault, &reward_vault])?;
+    result["pool"] = json!(pool.pubkey().to_string());
+    result["pool_signer"] = json!(pool_signer.to_string());
+    result["staking_vault"] = json!(staking_vault.pubkey().to_string());
+    result["reward_vault"] = json!(reward_vault.pubkey().to_string());
+    Ok(result)
+}
+
+/// Creates a mint with the staking mint's decimals, minted by the pool signer.
+fn enable_receipts(ctx: &Context, pool: &Pubkey) -> Result<Value> {
+    let pool_state = ctx.fetch_pool(pool)?;
+    let data = ctx.rpc.get_account_data(&pool_state.staking_mint)?.ok_or("staking mint not found")?;
+    let decimals = spl_token::state::Mint::unpack(&data)?.decimals;
+    let receipt_mint = Keypair::new();
+    let (pool_signer, _) = reward_pool_client::pool_signer_address(pool);
+    let lamports = ctx.rpc.get_minimum_balance_for_rent_exemption(spl_token::state::Mint::LEN)?;
+
+    let instructions = [
+        system_instruction::create_account(
+            &ctx.payer()?.pubkey(),
+            &receipt_mint.pubkey(),
+            lamports,
+            spl_token::state::Mint::LEN as u64,
+            &spl_token::ID,
+        ),
+        spl_token::instruction::initialize_mint(&spl_token::ID, &receipt_mint.pubkey(), &pool_signer, None, decimals)?,
+        reward_pool_client::enable_receipts(pool, &pool_state, &receipt_mint.pubkey()),
+    ];
+
+    let mut result = ctx.process(&instructions, &[&receipt_mint])?;
+    result["receipt_mint"] = json!(receipt_mint.pubkey().to_string());
+    Ok(result)
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let payer = if cli.command.is_read_only() {
+        None
+    } else {
+        let keypair_path = cli.keypair.unwrap_or_else(default_keypair_path);
+        let payer = read_keypair_file(&keypair_path)
+            .map_err(|err| format!("failed to read keypair {}: {}", keypair_path.display(), err))?;
+        Some(payer)
+    };
+    let signer = payer.as_ref().map(|x| x.pubkey()).unwrap_or_default();
+    let ctx = Context {
+        rpc: RpcClient::new(cluster_url(&cli.url)),
+        payer,
+        dry_run: cli.dry_run,
+        json: cli.json,
+    };
+
+    let result = match cli.command {
+        Command::CreatePool(args) => create_pool(&ctx, &args)?,
+        Command::Fund { pool, stream_index, amount, from } => {
+            let pool_state = ctx.fetch_pool(&pool)?;
+            if !pool_state.is_active_stream(stream_index) {
+                return Err(format!("stream {} is not active", stream_index).into());
+            }
+            ctx.process(
+                &[reward_pool_client::fund(&pool, &pool_state, stream_index, &signer, &from, amount)],
+                &[],
+            )?
+        }
+        Command::Pause { pool, flags } => {
+            ctx.process(&[reward_pool_client::pause(&pool, &signer, flags)], &[])?
+        }
+        Command::Unpause { pool, flags } => {
+            ctx.process(&[reward_pool_client::unpause(&pool, &signer, flags)], &[])?
+        }
+        Command::AuthorizeFunder { pool, funder } => {
+            ctx.process(&[reward_pool_client::authorize_funder(&pool, &signer, funder)], &[])?
+        }
+        Command::DeauthorizeFunder { pool, funder } => {
+            ctx.process(&[reward_pool_client::deauthorize_funder(&pool, &signer, funder)], &[])?
+        }
+        Command::EnableReceipts { pool } => enable_receipts(&ctx, &pool)?,
+        Command::ClosePool { pool, refundee, staking_refundee, reward_refundee, extra_reward_refundee } => {
+            let pool_state = ctx.fetch_pool(&pool)?;
+            let refundees = reward_pool_client::ClosePoolRefundees {
+                refundee: refundee.unwrap_or(signer),
+                staking_refundee,
+                reward_refundee,
+                extra_reward_refundees: extra_reward_refundee,
+            };
+            ctx.process(&[reward_pool_client::close_pool(&pool, &pool_state, &refundees)], &[])?
+        }
+        Command::InspectPool { pool } => pool_json(&pool, &ctx.fetch_pool(&pool)?),
+        Command::InspectUser { pool, owner } => {
+            let pool_state = ctx.fetch_pool(&pool)?;
+            let (user_key, _) = reward_pool_client::user_address(&owner, &pool);
+            let data = ctx.rpc.get_account_data(&user_key)?.ok_or("user account not found")?;
+            let user = reward_pool_client::deserialize_user(&data)?;
+            let pending = reward_pool_client::pending_rewards(&pool_state, &user, ctx.rpc.get_unix_timestamp()?)?;
+            user_json(&user_key, &user, &pending)
+        }
+        Command::ListUsers { pool } => {
+            let pool_state = ctx.fetch_pool(&pool)?;
+            let current_time = ctx.rpc.get_unix_timestamp()?;
+            let accounts = ctx.rpc.get_program_accounts(
+                &reward_pool::ID,
+                &[(0, &User::discriminator()), (8, pool.as_ref())],
+            )?;
+            let mut users = Vec::new();
+            for (user_key, data) in accounts {
+                let user = reward_pool_client::deserialize_user(&data)?;
+                let pending = reward_pool_client::pending_rewards(&pool_state, &user, current_time)?;
+                users.push(user_json(&user_key, &user, &pending));
+            }
+            json!({"pool": pool.to_string(), "users": users})
+        }
+    };
+
+    ctx.print(&result)
+}
+
+fn main() {
+    if let Err(err) = run(Cli::parse()) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_pool_args(extra: &[&str]) -> CreatePoolArgs {
+        let key = Pubkey::new_unique().to_string();
+        let mut args = vec![
+            "reward-pool",
+            "create-pool",
+            "--staking-mint",
+            &key,
+            "--reward-mint",
+            &key,
+            "--treasury",
+            &key,
+            "--reward-duration",
+            "86400",
+            "--lock-period",
+            "3600",
+        ];
+        args.extend_from_slice(extra);
+        match Cli::try_parse_from(args).unwrap().command {
+            Command::CreatePool(args) => args,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn default_flags_map_to_a_config_without_extras() {
+        let config = create_pool_args(&[]).config();
+        assert_eq!(config.max_lock_period, 3600);
+        assert_eq!(config.max_lock_multiplier_bps, BPS_DENOMINATOR);
+        assert!(config.penalty_curve == PenaltyCurve::Disabled);
+        assert_eq!(config.early_unstake_penalty_bps, 0);
+        assert!(config.penalty_destination == PenaltyDestination::Treasury);
+        assert!(config.reward_lock_policy == RewardLockPolicy::WithPrincipal);
+        assert_eq!(config.reward_lock_period, 0);
+        assert!(config.compound_resets_lock);
+        assert_eq!((config.vesting_period, config.vesting_cliff), (0, 0));
+    }
+
+    #[test]
+    fn flags_map_to_their_config_fields() {
+        let config = create_pool_args(&[
+            "--max-lock-period",
+            "7200",
+            "--max-lock-multiplier-bps",
+            "20000",
+            "--penalty-curve",
+            "linear-decay",
+            "--early-unstake-penalty-bps",
+            "500",
+            "--penalty-destination",
+            "stakers",
+            "--reward-lock-policy",
+            "independent",
+            "--reward-lock-period",
+            "600",
+            "--no-compound-lock-reset",
+            "--vesting-period",
+            "1000",
+            "--vesting-cliff",
+            "100",
+        ])
+        .config();
+        assert_eq!(config.max_lock_period, 7200);
+        assert_eq!(config.max_lock_multiplier_bps, 20000);
+        assert!(config.penalty_curve == PenaltyCurve::LinearDecay);
+        assert_eq!(config.early_unstake_penalty_bps, 500);
+        assert!(config.penalty_destination == PenaltyDestination::Stakers);
+        assert!(config.reward_lock_policy == RewardLockPolicy::Independent);
+        assert_eq!(config.reward_lock_period, 600);
+        assert!(!config.compound_resets_lock);
+        assert_eq!((config.vesting_period, config.vesting_cliff), (1000, 100));
+
+        let config = create_pool_args(&["--penalty-curve", "flat", "--reward-lock-policy", "anytime"]).config();
+        assert!(config.penalty_curve == PenaltyCurve::Flat);
+        assert!(config.reward_lock_policy == RewardLockPolicy::Anytime);
+    }
+
+    #[test]
+    fn cluster_monikers_resolve_to_their_urls() {
+        assert_eq!(cluster_url("mainnet"), "https://api.mainnet-beta.solana.com");
+        assert_eq!(cluster_url("m"), "https://api.mainnet-beta.solana.com");
+        assert_eq!(cluster_url("devnet"), "https://api.devnet.solana.com");
+        assert_eq!(cluster_url("d"), "https://api.devnet.solana.com");
+        assert_eq!(cluster_url("testnet"), "https://api.testnet.solana.com");
+        assert_eq!(cluster_url("t"), "https://api.testnet.solana.com");
+        assert_eq!(cluster_url("localnet"), "http://127.0.0.1:8899");
+        assert_eq!(cluster_url("l"), "http://127.0.0.1:8899");
+        assert_eq!(cluster_url("https://rpc.example.com"), "https://rpc.example.com");
+    }
+}
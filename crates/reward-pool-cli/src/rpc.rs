@@ -0,0 +1,150 @@
+//! The handful of JSON-RPC calls the tool needs.
+
+use serde_json::{json, Value};
+use solana_sdk::clock::Clock;
+use solana_sdk::hash::Hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::sysvar;
+use solana_sdk::transaction::Transaction;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::Result;
+
+const COMMITMENT: &str = "confirmed";
+const CONFIRM_ATTEMPTS: usize = 120;
+const CONFIRM_INTERVAL: Duration = Duration::from_millis(500);
+
+pub struct RpcClient {
+    url: String,
+}
+
+impl RpcClient {
+    pub fn new(url: String) -> Self {
+        RpcClient { url }
+    }
+
+    fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let response: Value = ureq::post(&self.url)
+            .send_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))?
+            .into_json()?;
+        if let Some(err) = response.get("error") {
+            return Err(format!("{} failed: {}", method, err).into());
+        }
+        Ok(response["result"].clone())
+    }
+
+    pub fn get_account_data(&self, pubkey: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let result = self.request(
+            "getAccountInfo",
+            json!([pubkey.to_string(), {"encoding": "base64", "commitment": COMMITMENT}]),
+        )?;
+        match result.get("value") {
+            Some(Value::Null) | None => Ok(None),
+            Some(account) => Ok(Some(decode_data(account)?)),
+        }
+    }
+
+    /// Accounts of `program_id` whose data matches every `(offset, bytes)` filter.
+    pub fn get_program_accounts(&self, program_id: &Pubkey, filters: &[(usize, &[u8])]) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+        let filters: Vec<Value> = filters
+            .iter()
+            .map(|(offset, bytes)| {
+                json!({"memcmp": {"offset": offset, "bytes": bs58_encode(bytes)}})
+            })
+            .collect();
+        let result = self.request(
+            "getProgramAccounts",
+            json!([program_id.to_string(), {
+                "encoding": "base64",
+                "commitment": COMMITMENT,
+                "filters": filters,
+            }]),
+        )?;
+
+        let mut accounts = Vec::new();
+        for entry in result.as_array().ok_or("unexpected getProgramAccounts response")? {
+            let pubkey = Pubkey::from_str(entry["pubkey"].as_str().ok_or("missing pubkey")?)?;
+            accounts.push((pubkey, decode_data(&entry["account"])?));
+        }
+        Ok(accounts)
+    }
+
+    /// The cluster's current time, read from the clock sysvar.
+    pub fn get_unix_timestamp(&self) -> Result<u64> {
+        let data = self.get_account_data(&sysvar::clock::ID)?.ok_or("clock sysvar not found")?;
+        let clock: Clock = bincode::deserialize(&data)?;
+        Ok(u64::try_from(clock.unix_timestamp)?)
+    }
+
+    pub fn get_minimum_balance_for_rent_exemption(&self, len: usize) -> Result<u64> {
+        let result = self.request("getMinimumBalanceForRentExemption", json!([len]))?;
+        result.as_u64().ok_or_else(|| "unexpected rent response".into())
+    }
+
+    pub fn get_latest_blockhash(&self) -> Result<Hash> {
+        let result = self.request("getLatestBlockhash", json!([{"commitment": COMMITMENT}]))?;
+        let blockhash = result["value"]["blockhash"].as_str().ok_or("missing blockhash")?;
+        Ok(Hash::from_str(blockhash)?)
+    }
+
+    /// Sends `transaction` and waits until it is confirmed.
+    pub fn send_and_confirm_transaction(&self, transaction: &Transaction) -> Result<Signature> {
+        let result = self.request(
+            "sendTransaction",
+            json!([encode_transaction(transaction)?, {"encoding": "base64", "preflightCommitment": COMMITMENT}]),
+        )?;
+        let signature = Signature::from_str(result.as_str().ok_or("missing signature")?)?;
+
+        for _ in 0..CONFIRM_ATTEMPTS {
+            let statuses = self.request("getSignatureStatuses", json!([[signature.to_string()]]))?;
+            let status = &statuses["value"][0];
+            if !status.is_null() {
+                if !status["err"].is_null() {
+                    return Err(format!("transaction {} failed: {}", signature, status["err"]).into());
+                }
+                let confirmation = status["confirmationStatus"].as_str().unwrap_or_default();
+                if confirmation == "confirmed" || confirmation == "finalized" {
+                    return Ok(signature);
+                }
+            }
+            sleep(CONFIRM_INTERVAL);
+        }
+        Err(format!("transaction {} was not confirmed", signature).into())
+    }
+
+    /// Simulates `transaction`, returning its error, if any, and logs.
+    pub fn simulate_transaction(&self, transaction: &Transaction) -> Result<(Value, Vec<String>)> {
+        let result = self.request(
+            "simulateTransaction",
+            json!([encode_transaction(transaction)?, {"encoding": "base64", "commitment": COMMITMENT}]),
+        )?;
+        let value = &result["value"];
+        let logs = value["logs"]
+            .as_array()
+            .map(|logs| logs.iter().filter_map(|x| x.as_str().map(String::from)).collect())
+            .unwrap_or_default();
+        Ok((value["err"].clone(), logs))
+    }
+}
+
+fn encode_transaction(transaction: &Transaction) -> Result<String> {
+    Ok(base64::encode(bincode::serialize(transaction)?))
+}
+
+fn decode_data(account: &Value) -> Result<Vec<u8>> {
+    let data = account["data"][0].as_str().ok_or("missing account data")?;
+    Ok(base64::decode(data)?)
+}
+
+fn bs58_encode(bytes: &[u8]) -> String {
+    solana_sdk::bs58::encode(bytes).into_string()
+}
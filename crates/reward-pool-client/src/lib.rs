@@ -0,0 +1,583 @@
+//! Builds reward pool instructions and reads its accounts.
+//!
+//! Instructions acting on an existing pool take the deserialized `Pool` so vaults
+//! and other pool keys don't have to be passed around separately.
+
+use anchor_lang::{AccountDeserialize, AnchorSerialize, InstructionData, ToAccountMetas};
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::{system_instruction, system_program};
+
+pub use reward_pool::{self, pending_rewards, Pool, PoolConfig, RewardStream, User, Vesting, ID};
+pub use reward_pool_math;
+
+/// Size of a pool account, its discriminator and serialized fields. The pool is
+/// created by the caller before `initialize_pool`.
+pub fn pool_account_size() -> usize {
+    8 + Pool::default().try_to_vec().expect("pool serializes").len()
+}
+
+/// The program signer owning a pool's vaults, seeds `[pool]`.
+pub fn pool_signer_address(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[pool.as_ref()], &ID)
+}
+
+/// A user's stake account, seeds `[owner, pool]`.
+pub fn user_address(owner: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[owner.as_ref(), pool.as_ref()], &ID)
+}
+
+/// A user's vesting account, seeds `[owner, pool, "vesting"]`.
+pub fn vesting_address(owner: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[owner.as_ref(), pool.as_ref(), b"vesting"], &ID)
+}
+
+pub fn deserialize_pool(data: &[u8]) -> Result<Pool, ProgramError> {
+    Pool::try_deserialize(&mut &data[..])
+}
+
+pub fn deserialize_user(data: &[u8]) -> Result<User, ProgramError> {
+    User::try_deserialize(&mut &data[..])
+}
+
+pub fn deserialize_vesting(data: &[u8]) -> Result<Vesting, ProgramError> {
+    Vesting::try_deserialize(&mut &data[..])
+}
+
+fn instruction(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Creates the account `initialize_pool` writes the pool into.
+pub fn create_pool_account(payer: &Pubkey, pool: &Pubkey, lamports: u64) -> Instruction {
+    system_instruction::create_account(payer, pool, lamports, pool_account_size() as u64, &ID)
+}
+
+/// Accounts of a new pool. The vaults and treasury must already exist.
+pub struct InitializePoolAccounts {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub staking_mint: Pubkey,
+    pub staking_vault: Pubkey,
+    pub reward_mint: Pubkey,
+    pub reward_vault: Pubkey,
+    pub treasury: Pubkey,
+}
+
+pub fn initialize_pool(
+    accounts: &InitializePoolAccounts,
+    reward_duration: u64,
+    lock_period: u64,
+    config: PoolConfig,
+) -> Instruction {
+    let (pool_signer, pool_nonce) = pool_signer_address(&accounts.pool);
+    instruction(
+        reward_pool::accounts::InitializePool {
+            authority: accounts.authority,
+            staking_mint: accounts.staking_mint,
+            staking_vault: accounts.staking_vault,
+            reward_mint: accounts.reward_mint,
+            reward_vault: accounts.reward_vault,
+            treasury: accounts.treasury,
+            pool_signer,
+            pool: accounts.pool,
+            token_program: spl_token::ID,
+        },
+        reward_pool::instruction::InitializePool {
+            pool_nonce,
+            reward_duration,
+            lock_period,
+            config,
+        },
+    )
+}
+
+pub fn update_pool_config(
+    pool: &Pubkey,
+    authority: &Pubkey,
+    reward_duration: Option<u64>,
+    lock_period: Option<u64>,
+    config: Option<PoolConfig>,
+) -> Instruction {
+    instruction(
+        reward_pool::accounts::UpdatePoolConfig {
+            pool: *pool,
+            authority: *authority,
+        },
+        reward_pool::instruction::UpdatePoolConfig {
+            reward_duration,
+            lock_period,
+            config,
+        },
+    )
+}
+
+pub fn create_user(pool: &Pubkey, owner: &Pubkey) -> Instruction {
+    let (user, nonce) = user_address(owner, pool);
+    instruction(
+        reward_pool::accounts::CreateUser {
+            pool: *pool,
+            user,
+            owner: *owner,
+            system_program: system_program::ID,
+        },
+        reward_pool::instruction::CreateUser { nonce },
+    )
+}
+
+pub fn create_vesting(pool: &Pubkey, owner: &Pubkey) -> Instruction {
+    let (vesting, nonce) = vesting_address(owner, pool);
+    instruction(
+        reward_pool::accounts::CreateVesting {
+            pool: *pool,
+            vesting,
+            owner: *owner,
+            system_program: system_program::ID,
+        },
+        reward_pool::instruction::CreateVesting { nonce },
+    )
+}
+
+pub fn view_pending(pool: &Pubkey, user: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::ViewPending {
+            pool: *pool,
+            user: *user,
+        },
+        reward_pool::instruction::ViewPending {},
+    )
+}
+
+pub fn pause(pool: &Pubkey, pauser: &Pubkey, flags: u8) -> Instruction {
+    instruction(
+        reward_pool::accounts::Pause {
+            pool: *pool,
+            pauser: *pauser,
+            pool_signer: pool_signer_address(pool).0,
+            token_program: spl_token::ID,
+        },
+        reward_pool::instruction::Pause { flags },
+    )
+}
+
+pub fn unpause(pool: &Pubkey, authority: &Pubkey, flags: u8) -> Instruction {
+    instruction(
+        reward_pool::accounts::Unpause {
+            pool: *pool,
+            authority: *authority,
+            pool_signer: pool_signer_address(pool).0,
+            token_program: spl_token::ID,
+        },
+        reward_pool::instruction::Unpause { flags },
+    )
+}
+
+pub fn set_guardian(pool: &Pubkey, authority: &Pubkey, guardian: Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::SetGuardian {
+            pool: *pool,
+            authority: *authority,
+        },
+        reward_pool::instruction::SetGuardian { guardian },
+    )
+}
+
+pub fn set_emergency(pool: &Pubkey, authority: &Pubkey, enabled: bool) -> Instruction {
+    instruction(
+        reward_pool::accounts::SetEmergency {
+            pool: *pool,
+            authority: *authority,
+        },
+        reward_pool::instruction::SetEmergency { enabled },
+    )
+}
+
+fn stake_accounts(pool: &Pubkey, pool_state: &Pool, owner: &Pubkey, stake_from_account: &Pubkey) -> reward_pool::accounts::Stake {
+    reward_pool::accounts::Stake {
+        pool: *pool,
+        staking_vault: pool_state.staking_vault,
+        user: user_address(owner, pool).0,
+        owner: *owner,
+        stake_from_account: *stake_from_account,
+        pool_signer: pool_signer_address(pool).0,
+        token_program: spl_token::ID,
+    }
+}
+
+/// Appends the receipt mint and the owner's receipt account in pools issuing receipts.
+fn with_receipt_accounts(mut ix: Instruction, pool_state: &Pool, owner: &Pubkey) -> Instruction {
+    if pool_state.issues_receipts() {
+        ix.accounts.push(AccountMeta::new(pool_state.receipt_mint, false));
+        ix.accounts.push(AccountMeta::new(pool_state.receipt_account(owner), false));
+    }
+    ix
+}
+
+/// In pools issuing receipts, the owner's receipt account must exist.
+pub fn stake(
+    pool: &Pubkey,
+    pool_state: &Pool,
+    owner: &Pubkey,
+    stake_from_account: &Pubkey,
+    amount: u64,
+    lock_duration: u64,
+) -> Instruction {
+    let ix = instruction(
+        stake_accounts(pool, pool_state, owner, stake_from_account),
+        reward_pool::instruction::Stake { amount, lock_duration },
+    );
+    with_receipt_accounts(ix, pool_state, owner)
+}
+
+pub fn unstake(pool: &Pubkey, pool_state: &Pool, owner: &Pubkey, stake_to_account: &Pubkey, spt_amount: u64) -> Instruction {
+    let ix = instruction(
+        stake_accounts(pool, pool_state, owner, stake_to_account),
+        reward_pool::instruction::Unstake { spt_amount },
+    );
+    with_receipt_accounts(ix, pool_state, owner)
+}
+
+pub fn emergency_withdraw(pool: &Pubkey, pool_state: &Pool, owner: &Pubkey, stake_to_account: &Pubkey) -> Instruction {
+    let ix = instruction(
+        stake_accounts(pool, pool_state, owner, stake_to_account),
+        reward_pool::instruction::EmergencyWithdraw {},
+    );
+    with_receipt_accounts(ix, pool_state, owner)
+}
+
+/// `receipt_mint` must be an empty mint of the staking mint's decimals, with the
+/// pool signer as mint authority and no freeze authority.
+pub fn enable_receipts(pool: &Pubkey, pool_state: &Pool, receipt_mint: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::EnableReceipts {
+            pool: *pool,
+            authority: pool_state.authority,
+            staking_mint: pool_state.staking_mint,
+            receipt_mint: *receipt_mint,
+            pool_signer: pool_signer_address(pool).0,
+        },
+        reward_pool::instruction::EnableReceipts {},
+    )
+}
+
+pub fn sync_receipt(pool: &Pubkey, pool_state: &Pool, owner: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::SyncReceipt {
+            pool: *pool,
+            receipt_mint: pool_state.receipt_mint,
+            user: user_address(owner, pool).0,
+            receipt_account: pool_state.receipt_account(owner),
+        },
+        reward_pool::instruction::SyncReceipt {},
+    )
+}
+
+/// Transfers receipts between two holders' receipt accounts and syncs both, so the
+/// rewards of the transferred stake go to the recipient from then on. Both holders
+/// need a user account and the recipient's receipt account must exist.
+pub fn transfer_receipt(
+    pool: &Pubkey,
+    pool_state: &Pool,
+    from_owner: &Pubkey,
+    to_owner: &Pubkey,
+    amount: u64,
+) -> Result<Vec<Instruction>, ProgramError> {
+    Ok(vec![
+        spl_token::instruction::transfer(
+            &spl_token::ID,
+            &pool_state.receipt_account(from_owner),
+            &pool_state.receipt_account(to_owner),
+            from_owner,
+            &[],
+            amount,
+        )?,
+        sync_receipt(pool, pool_state, from_owner),
+        sync_receipt(pool, pool_state, to_owner),
+    ])
+}
+
+pub fn early_unstake(pool: &Pubkey, pool_state: &Pool, owner: &Pubkey, stake_to_account: &Pubkey, spt_amount: u64) -> Instruction {
+    instruction(
+        reward_pool::accounts::EarlyUnstake {
+            pool: *pool,
+            staking_vault: pool_state.staking_vault,
+            penalty_vault: pool_state.penalty_vault(),
+            user: user_address(owner, pool).0,
+            owner: *owner,
+            stake_from_account: *stake_to_account,
+            pool_signer: pool_signer_address(pool).0,
+            token_program: spl_token::ID,
+        },
+        reward_pool::instruction::EarlyUnstake { spt_amount },
+    )
+}
+
+pub fn sweep_excess_stake(pool: &Pubkey, pool_state: &Pool, destination: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::SweepExcessStake {
+            pool: *pool,
+            authority: pool_state.authority,
+            staking_vault: pool_state.staking_vault,
+            destination: *destination,
+            pool_signer: pool_signer_address(pool).0,
+            token_program: spl_token::ID,
+        },
+        reward_pool::instruction::SweepExcessStake {},
+    )
+}
+
+pub fn recover_undistributed(pool: &Pubkey, pool_state: &Pool, stream_index: u8, destination: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::RecoverUndistributed {
+            pool: *pool,
+            authority: pool_state.authority,
+            reward_vault: pool_state.reward_streams[stream_index as usize].vault,
+            destination: *destination,
+            pool_signer: pool_signer_address(pool).0,
+            token_program: spl_token::ID,
+        },
+        reward_pool::instruction::RecoverUndistributed { stream_index },
+    )
+}
+
+pub fn propose_authority(pool: &Pubkey, authority: &Pubkey, new_authority: Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::ProposeAuthority {
+            pool: *pool,
+            authority: *authority,
+        },
+        reward_pool::instruction::ProposeAuthority { new_authority },
+    )
+}
+
+pub fn accept_authority(pool: &Pubkey, pending_authority: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::AcceptAuthority {
+            pool: *pool,
+            pending_authority: *pending_authority,
+        },
+        reward_pool::instruction::AcceptAuthority {},
+    )
+}
+
+pub fn cancel_authority_transfer(pool: &Pubkey, authority: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::ProposeAuthority {
+            pool: *pool,
+            authority: *authority,
+        },
+        reward_pool::instruction::CancelAuthorityTransfer {},
+    )
+}
+
+pub fn authorize_funder(pool: &Pubkey, authority: &Pubkey, funder_to_add: Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::FunderChange {
+            pool: *pool,
+            authority: *authority,
+        },
+        reward_pool::instruction::AuthorizeFunder { funder_to_add },
+    )
+}
+
+pub fn deauthorize_funder(pool: &Pubkey, authority: &Pubkey, funder_to_remove: Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::FunderChange {
+            pool: *pool,
+            authority: *authority,
+        },
+        reward_pool::instruction::DeauthorizeFunder { funder_to_remove },
+    )
+}
+
+/// Adds a stream paying `reward_mint` from `reward_vault`, which must be owned by the pool signer.
+pub fn add_reward_stream(pool: &Pubkey, authority: &Pubkey, reward_mint: &Pubkey, reward_vault: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::AddRewardStream {
+            pool: *pool,
+            authority: *authority,
+            reward_mint: *reward_mint,
+            reward_vault: *reward_vault,
+            pool_signer: pool_signer_address(pool).0,
+        },
+        reward_pool::instruction::AddRewardStream {},
+    )
+}
+
+fn fund_accounts(pool: &Pubkey, pool_state: &Pool, stream_index: u8, funder: &Pubkey, from: &Pubkey) -> reward_pool::accounts::Fund {
+    reward_pool::accounts::Fund {
+        pool: *pool,
+        staking_vault: pool_state.staking_vault,
+        reward_vault: pool_state.reward_streams[stream_index as usize].vault,
+        funder: *funder,
+        from: *from,
+        pool_signer: pool_signer_address(pool).0,
+        token_program: spl_token::ID,
+    }
+}
+
+pub fn fund(pool: &Pubkey, pool_state: &Pool, stream_index: u8, funder: &Pubkey, from: &Pubkey, amount: u64) -> Instruction {
+    instruction(
+        fund_accounts(pool, pool_state, stream_index, funder, from),
+        reward_pool::instruction::Fund { stream_index, amount },
+    )
+}
+
+/// Funds a campaign emitting between `start_ts` and `end_ts`.
+pub struct FundSchedule {
+    pub stream_index: u8,
+    pub amount: u64,
+    pub start_ts: u64,
+    pub end_ts: u64,
+}
+
+pub fn fund_scheduled(pool: &Pubkey, pool_state: &Pool, funder: &Pubkey, from: &Pubkey, schedule: &FundSchedule) -> Instruction {
+    instruction(
+        fund_accounts(pool, pool_state, schedule.stream_index, funder, from),
+        reward_pool::instruction::FundScheduled {
+            stream_index: schedule.stream_index,
+            amount: schedule.amount,
+            start_ts: schedule.start_ts,
+            end_ts: schedule.end_ts,
+        },
+    )
+}
+
+pub fn claim(pool: &Pubkey, pool_state: &Pool, stream_index: u8, owner: &Pubkey, reward_account: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::ClaimReward {
+            pool: *pool,
+            staking_vault: pool_state.staking_vault,
+            reward_vault: pool_state.reward_streams[stream_index as usize].vault,
+            user: user_address(owner, pool).0,
+            owner: *owner,
+            reward_account: *reward_account,
+            pool_signer: pool_signer_address(pool).0,
+            token_program: spl_token::ID,
+        },
+        reward_pool::instruction::Claim { stream_index },
+    )
+}
+
+/// Claims every active stream. `reward_accounts` receive the rewards of the
+/// active streams, in stream order.
+pub fn claim_all(pool: &Pubkey, pool_state: &Pool, owner: &Pubkey, reward_accounts: &[Pubkey]) -> Instruction {
+    let mut ix = instruction(
+        reward_pool::accounts::ClaimAll {
+            pool: *pool,
+            user: user_address(owner, pool).0,
+            owner: *owner,
+            pool_signer: pool_signer_address(pool).0,
+            token_program: spl_token::ID,
+        },
+        reward_pool::instruction::ClaimAll {},
+    );
+    let vaults = pool_state.reward_streams.iter().filter(|x| x.is_active()).map(|x| x.vault);
+    for (vault, reward_account) in vaults.zip(reward_accounts) {
+        ix.accounts.push(AccountMeta::new(vault, false));
+        ix.accounts.push(AccountMeta::new(*reward_account, false));
+    }
+    ix
+}
+
+pub fn claim_vesting(pool: &Pubkey, stream_index: u8, owner: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::ClaimVesting {
+            pool: *pool,
+            user: user_address(owner, pool).0,
+            vesting: vesting_address(owner, pool).0,
+            owner: *owner,
+        },
+        reward_pool::instruction::ClaimVesting { stream_index },
+    )
+}
+
+pub fn withdraw_vested(pool: &Pubkey, pool_state: &Pool, stream_index: u8, owner: &Pubkey, reward_account: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::WithdrawVested {
+            pool: *pool,
+            reward_vault: pool_state.reward_streams[stream_index as usize].vault,
+            vesting: vesting_address(owner, pool).0,
+            owner: *owner,
+            reward_account: *reward_account,
+            pool_signer: pool_signer_address(pool).0,
+            token_program: spl_token::ID,
+        },
+        reward_pool::instruction::WithdrawVested { stream_index },
+    )
+}
+
+pub fn compound(pool: &Pubkey, pool_state: &Pool, stream_index: u8, owner: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::Compound {
+            pool: *pool,
+            staking_vault: pool_state.staking_vault,
+            reward_vault: pool_state.reward_streams[stream_index as usize].vault,
+            user: user_address(owner, pool).0,
+            owner: *owner,
+            pool_signer: pool_signer_address(pool).0,
+            token_program: spl_token::ID,
+        },
+        reward_pool::instruction::Compound { stream_index },
+    )
+}
+
+pub fn close_user(pool: &Pubkey, owner: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::CloseUser {
+            pool: *pool,
+            user: user_address(owner, pool).0,
+            owner: *owner,
+        },
+        reward_pool::instruction::CloseUser {},
+    )
+}
+
+pub fn close_vesting(pool: &Pubkey, owner: &Pubkey) -> Instruction {
+    instruction(
+        reward_pool::accounts::CloseVesting {
+            pool: *pool,
+            vesting: vesting_address(owner, pool).0,
+            owner: *owner,
+        },
+        reward_pool::instruction::CloseVesting {},
+    )
+}
+
+/// Where the lamports and remaining tokens of a closed pool go.
+pub struct ClosePoolRefundees {
+    pub refundee: Pubkey,
+    pub staking_refundee: Pubkey,
+    /// Receives the first stream's rewards.
+    pub reward_refundee: Pubkey,
+    /// Receive the rewards of the other active streams, in stream order.
+    pub extra_reward_refundees: Vec<Pubkey>,
+}
+
+pub fn close_pool(pool: &Pubkey, pool_state: &Pool, refundees: &ClosePoolRefundees) -> Instruction {
+    let mut ix = instruction(
+        reward_pool::accounts::ClosePool {
+            refundee: refundees.refundee,
+            staking_refundee: refundees.staking_refundee,
+            reward_refundee: refundees.reward_refundee,
+            pool: *pool,
+            authority: pool_state.authority,
+            staking_vault: pool_state.staking_vault,
+            reward_vault: pool_state.reward_streams[0].vault,
+            pool_signer: pool_signer_address(pool).0,
+            token_program: spl_token::ID,
+        },
+        reward_pool::instruction::ClosePool {},
+    );
+    let vaults = pool_state.reward_streams.iter().skip(1).filter(|x| x.is_active()).map(|x| x.vault);
+    for (vault, refundee) in vaults.zip(&refundees.extra_reward_refundees) {
+        ix.accounts.push(AccountMeta::new(vault, false));
+        ix.accounts.push(AccountMeta::new(*refundee, false));
+    }
+    ix
+}
@@ -0,0 +1,1469 @@
+//! Runs the reward pool program in a local bank, warping the clock to check
+//! exact reward accounting across lock and reward periods.
+
+use anchor_lang::prelude::borsh;
+use anchor_lang::{AnchorDeserialize, AnchorSerialize, Discriminator};
+use reward_pool_client::reward_pool::{
+    self, ErrorCode, PenaltyCurve, PenaltyDestination, PendingRewards, PoolFunded, RewardLockPolicy, RewardPaid,
+    Staked, MAX_STAKE_POSITIONS, PAUSE_ALL, PAUSE_CLAIMS, PAUSE_DEPOSITS, PAUSE_FUNDING, PAUSE_NEW_USERS,
+    PAUSE_WITHDRAWALS, PRECISION,
+};
+use reward_pool_client::{ClosePoolRefundees, FundSchedule, InitializePoolAccounts, Pool, PoolConfig};
+use reward_pool_test_utils::{
+    assert_error, config, error_code, events, record_events, return_data, TestPool, FUND_AMOUNT, LOCK_PERIOD,
+    REWARD_DURATION, START,
+};
+use solana_sdk::account::AccountSharedData;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+
+#[tokio::test]
+async fn rewards_split_by_stake_over_time() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    let bob = test.staker(3_000).await;
+
+    test.fund(FUND_AMOUNT).await.unwrap();
+    test.stake(&alice, 1_000).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!(pool.user_stake_count, 2);
+    assert_eq!(pool.reward_streams[0].reward_rate, 1_000 * PRECISION);
+    assert_eq!(pool.reward_streams[0].reward_duration_end, START as u64 + REWARD_DURATION);
+
+    // Neither principal nor rewards are available before the lock period ends.
+    test.warp_to(START + 1_800).await;
+    assert_error(test.unstake(&alice, 1_000).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+    assert_error(test.claim(&alice).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+
+    // Alice earns everything for a quarter of the period, then shares it 1:3 with Bob.
+    test.warp_to(START + 21_600).await;
+    test.stake(&bob, 3_000).await.unwrap();
+    assert_eq!(test.pool_state().await.reward_streams[0].reward_per_token_stored, 21_600 * PRECISION);
+    assert_eq!(test.token_balance(&test.staking_vault.clone()).await, 4_000);
+
+    test.warp_to(START + 43_200).await;
+    assert_eq!(test.pending(&alice).await, 27_000_000);
+    assert_eq!(test.pending(&bob).await, 16_200_000);
+    test.claim(&alice).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!(pool.reward_streams[0].reward_per_token_stored, 27_000 * PRECISION);
+    assert_eq!(pool.reward_streams[0].reward_liability, 16_200_000);
+    assert_eq!(test.token_balance(&alice.reward_account).await, 27_000_000);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, FUND_AMOUNT - 27_000_000);
+
+    // Nothing accrues after the period ends.
+    test.warp_to(START + REWARD_DURATION as i64 + 600).await;
+    test.unstake(&alice, 1_000).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!(pool.reward_streams[0].reward_per_token_stored, 37_800 * PRECISION);
+    assert_eq!(pool.total_staked, 3_000);
+    assert_eq!(test.user_state(&alice).await.reward_per_token_pending[0], 10_800_000);
+    assert_eq!(test.token_balance(&alice.stake_account).await, 1_000);
+    assert_eq!(test.token_balance(&test.staking_vault.clone()).await, 3_000);
+
+    test.claim(&bob).await.unwrap();
+    test.claim(&alice).await.unwrap();
+    assert_eq!(test.token_balance(&bob.reward_account).await, 48_600_000);
+    assert_eq!(test.token_balance(&alice.reward_account).await, 37_800_000);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, 0);
+    assert_eq!(test.pool_state().await.reward_streams[0].reward_liability, 0);
+}
+
+#[tokio::test]
+async fn unstaked_emissions_roll_into_next_funding() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+
+    // Half the period passes with nobody staked.
+    test.fund(FUND_AMOUNT).await.unwrap();
+    test.warp_to(START + 43_200).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!(pool.reward_streams[0].undistributed, 43_200_000);
+    assert_eq!(pool.reward_streams[0].reward_per_token_stored, 0);
+
+    test.warp_to(START + REWARD_DURATION as i64).await;
+    assert_eq!(test.pending(&alice).await, 43_200_000);
+
+    // Funding nothing restarts the period with the undistributed half.
+    test.warp_to(START + REWARD_DURATION as i64 + 100).await;
+    test.fund(0).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!(pool.reward_streams[0].undistributed, 0);
+    assert_eq!(pool.reward_streams[0].reward_rate, 500 * PRECISION);
+    assert_eq!(pool.reward_streams[0].reward_liability, 43_200_000);
+
+    test.warp_to(START + 2 * REWARD_DURATION as i64 + 100).await;
+    test.claim(&alice).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, FUND_AMOUNT);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, 0);
+}
+
+#[tokio::test]
+async fn funding_mid_period_carries_leftover_rewards() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    test.warp_to(START + 43_200).await;
+    test.fund(FUND_AMOUNT).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!(pool.reward_streams[0].reward_rate, 1_500 * PRECISION);
+    assert_eq!(pool.reward_streams[0].reward_per_token_stored, 43_200 * PRECISION);
+    assert_eq!(pool.reward_streams[0].reward_duration_end, START as u64 + 43_200 + REWARD_DURATION);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, 2 * FUND_AMOUNT);
+
+    test.warp_to(START + 43_200 + REWARD_DURATION as i64).await;
+    assert_eq!(test.pending(&alice).await, 2 * FUND_AMOUNT);
+    test.claim(&alice).await.unwrap();
+    test.unstake(&alice, 1_000).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, 2 * FUND_AMOUNT);
+    assert_eq!(test.token_balance(&alice.stake_account).await, 1_000);
+    assert_eq!(test.pool_state().await.total_staked, 0);
+}
+
+#[tokio::test]
+async fn receipt_transfers_carry_rewards() {
+    let mut test = TestPool::with_lock_period(0).await;
+    let receipt_mint = test.enable_receipts().await;
+    let alice = test.staker(1_000).await;
+    let bob = test.staker(0).await;
+    let alice_receipts = test.create_receipt_account(&alice).await;
+    let bob_receipts = test.create_receipt_account(&bob).await;
+
+    // Receipts can be redeemed by whoever holds them, so locks stay off.
+    let instruction = reward_pool_client::update_pool_config(
+        &test.pool,
+        &test.payer(),
+        None,
+        Some(LOCK_PERIOD),
+        Some(config(LOCK_PERIOD)),
+    );
+    assert_error(test.process(&[instruction], &[]).await, ErrorCode::InvalidReceiptConfig);
+
+    test.fund(FUND_AMOUNT).await.unwrap();
+    test.stake(&alice, 1_000).await.unwrap();
+    assert_eq!(test.token_balance(&alice_receipts).await, 1_000);
+
+    // Received receipts don't count until the sender is synced, so none count twice.
+    test.warp_to(START + 21_600).await;
+    let instruction =
+        spl_token::instruction::transfer(&spl_token::ID, &alice_receipts, &bob_receipts, &alice.owner.pubkey(), &[], 250)
+            .unwrap();
+    test.process(&[instruction], &[&alice.owner]).await.unwrap();
+    test.sync_receipt(&bob).await.unwrap();
+    assert_eq!(test.user_state(&bob).await.balance_staked, 0);
+    let pool = test.pool_state().await;
+    let instructions = [
+        reward_pool_client::sync_receipt(&test.pool, &pool, &alice.owner.pubkey()),
+        reward_pool_client::sync_receipt(&test.pool, &pool, &bob.owner.pubkey()),
+    ];
+    test.process(&instructions, &[]).await.unwrap();
+    assert_eq!(test.user_state(&alice).await.balance_staked, 750);
+    assert_eq!(test.user_state(&bob).await.balance_staked, 250);
+    assert_eq!(test.pool_state().await.total_weighted_stake, 1_000);
+
+    test.warp_to(START + 43_200).await;
+    assert_eq!(test.pending(&alice).await, 37_800_000);
+    assert_eq!(test.pending(&bob).await, 5_400_000);
+
+    // Bob redeems the receipts he was sent, and no more.
+    test.unstake(&bob, 250).await.unwrap();
+    assert_error(test.unstake(&bob, 1).await, ErrorCode::InsufficientFundUnstake);
+    assert_eq!(test.token_balance(&bob.stake_account).await, 250);
+    assert_eq!(test.token_balance(&bob_receipts).await, 0);
+    let pool = test.pool_state().await;
+    assert_eq!(pool.total_staked, 750);
+    assert_eq!(pool.total_weighted_stake, 750);
+
+    let pool = test.pool_state().await;
+    let instructions =
+        reward_pool_client::transfer_receipt(&test.pool, &pool, &alice.owner.pubkey(), &bob.owner.pubkey(), 250).unwrap();
+    test.process(&instructions, &[&alice.owner]).await.unwrap();
+
+    test.warp_to(START + 64_800).await;
+    assert_eq!(test.pending(&alice).await, 52_200_000);
+    assert_eq!(test.pending(&bob).await, 12_600_000);
+    test.unstake(&alice, 500).await.unwrap();
+    test.unstake(&bob, 250).await.unwrap();
+    assert_eq!(test.token_balance(&test.staking_vault.clone()).await, 0);
+    let account = test.context.banks_client.get_account(receipt_mint).await.unwrap().unwrap();
+    assert_eq!(spl_token::state::Mint::unpack(&account.data).unwrap().supply, 0);
+}
+
+#[tokio::test]
+async fn uneven_rewards_stay_covered_by_liability() {
+    let mut test = TestPool::with_lock_period(0).await;
+    let alice = test.staker(5).await;
+    let bob = test.staker(5).await;
+
+    // Emits just under 3 tokens a second, so flooring each second's emission
+    // loses almost a token while Alice's half of it floors evenly.
+    let amount = 3 * REWARD_DURATION - 1;
+    test.stake(&alice, 5).await.unwrap();
+    test.stake(&bob, 5).await.unwrap();
+    test.fund(amount).await.unwrap();
+    for elapsed in 1..=10 {
+        test.warp_to(START + elapsed).await;
+        test.claim(&alice).await.unwrap();
+    }
+    assert_eq!(test.token_balance(&alice.reward_account).await, 10);
+
+    // Bob settles all of his share at once and is still covered.
+    let pending = test.pending(&bob).await;
+    assert_eq!(pending, 14);
+    assert!(test.pool_state().await.reward_streams[0].reward_liability >= pending);
+    test.claim(&bob).await.unwrap();
+    assert_eq!(test.token_balance(&bob.reward_account).await, 14);
+
+    test.warp_to(START + REWARD_DURATION as i64).await;
+    test.claim(&alice).await.unwrap();
+    test.claim(&bob).await.unwrap();
+    let paid = test.token_balance(&alice.reward_account).await + test.token_balance(&bob.reward_account).await;
+    let pool = test.pool_state().await;
+    // Each of the 13 settlements floors away less than a token.
+    assert!(amount - paid < 13);
+    assert!(pool.reward_streams[0].reward_liability <= amount - paid);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, amount - paid);
+}
+
+#[tokio::test]
+async fn pause_flags_block_their_operations() {
+    let mut test = TestPool::compounding(0).await;
+    let alice = test.staker(2_000).await;
+    let guardian = Keypair::new();
+    let instruction = reward_pool_client::set_guardian(&test.pool, &test.payer(), guardian.pubkey());
+    test.process(&[instruction], &[]).await.unwrap();
+
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+    test.warp_to(START + 100).await;
+
+    // Compounding both deposits and claims, it stops when either is paused.
+    test.pause(&guardian, PAUSE_DEPOSITS).await.unwrap();
+    assert_error(test.stake(&alice, 500).await, ErrorCode::PoolPaused);
+    assert_error(test.compound(&alice).await, ErrorCode::PoolPaused);
+    test.claim(&alice).await.unwrap();
+    test.unpause(PAUSE_DEPOSITS).await.unwrap();
+
+    // A new slot, as transactions repeated within one are dropped.
+    test.warp_to(START + 200).await;
+    test.pause(&guardian, PAUSE_CLAIMS).await.unwrap();
+    assert_error(test.claim(&alice).await, ErrorCode::PoolPaused);
+    assert_error(test.compound(&alice).await, ErrorCode::PoolPaused);
+    test.stake(&alice, 500).await.unwrap();
+    test.unpause(PAUSE_CLAIMS).await.unwrap();
+
+    test.warp_to(START + 300).await;
+    test.pause(&guardian, PAUSE_WITHDRAWALS).await.unwrap();
+    assert_error(test.unstake(&alice, 500).await, ErrorCode::PoolPaused);
+    assert_error(test.early_unstake(&alice, 500).await, ErrorCode::PoolPaused);
+    test.compound(&alice).await.unwrap();
+    test.unpause(PAUSE_WITHDRAWALS).await.unwrap();
+
+    test.warp_to(START + 400).await;
+    test.pause(&guardian, PAUSE_FUNDING).await.unwrap();
+    assert_error(test.fund(FUND_AMOUNT).await, anchor_lang::__private::ErrorCode::ConstraintRaw);
+    test.unpause(PAUSE_FUNDING).await.unwrap();
+
+    test.warp_to(START + 500).await;
+    test.pause(&guardian, PAUSE_NEW_USERS).await.unwrap();
+    let bob = Keypair::new();
+    let instructions = [
+        system_instruction::transfer(&test.payer(), &bob.pubkey(), 1_000_000_000),
+        reward_pool_client::create_user(&test.pool, &bob.pubkey()),
+    ];
+    assert_error(test.process(&instructions, &[&bob]).await, anchor_lang::__private::ErrorCode::ConstraintRaw);
+    test.unpause(PAUSE_NEW_USERS).await.unwrap();
+
+    test.warp_to(START + 600).await;
+    test.unstake(&alice, 500).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+    test.create_user(&bob).await;
+    assert_eq!(test.pool_state().await.pause_flags, 0);
+}
+
+#[tokio::test]
+async fn guardian_pauses_but_only_the_authority_unpauses() {
+    let mut test = TestPool::new().await;
+    let guardian = Keypair::new();
+    let stranger = Keypair::new();
+
+    // Nobody but the authority may pause before a guardian is set.
+    assert_error(test.pause(&guardian, PAUSE_DEPOSITS).await, anchor_lang::__private::ErrorCode::ConstraintRaw);
+    let instruction = reward_pool_client::set_guardian(&test.pool, &test.payer(), guardian.pubkey());
+    test.process(&[instruction], &[]).await.unwrap();
+
+    assert_error(test.pause(&guardian, 0).await, ErrorCode::InvalidPauseFlags);
+    assert_error(test.pause(&guardian, PAUSE_ALL + 1).await, ErrorCode::InvalidPauseFlags);
+    assert_error(test.pause(&stranger, PAUSE_DEPOSITS).await, anchor_lang::__private::ErrorCode::ConstraintRaw);
+    test.pause(&guardian, PAUSE_DEPOSITS | PAUSE_CLAIMS).await.unwrap();
+    assert_eq!(test.pool_state().await.pause_flags, PAUSE_DEPOSITS | PAUSE_CLAIMS);
+
+    let instruction = reward_pool_client::unpause(&test.pool, &guardian.pubkey(), PAUSE_DEPOSITS);
+    assert_error(
+        test.process(&[instruction], &[&guardian]).await,
+        anchor_lang::__private::ErrorCode::ConstraintHasOne,
+    );
+    test.unpause(PAUSE_DEPOSITS).await.unwrap();
+    assert_eq!(test.pool_state().await.pause_flags, PAUSE_CLAIMS);
+
+    // Removing the guardian revokes its pause rights.
+    let instruction = reward_pool_client::set_guardian(&test.pool, &test.payer(), Pubkey::default());
+    test.process(&[instruction], &[]).await.unwrap();
+    test.warp_to(START + 100).await;
+    assert_error(test.pause(&guardian, PAUSE_DEPOSITS).await, anchor_lang::__private::ErrorCode::ConstraintRaw);
+    let instruction = reward_pool_client::pause(&test.pool, &test.payer(), PAUSE_ALL);
+    test.process(&[instruction], &[]).await.unwrap();
+    assert_eq!(test.pool_state().await.pause_flags, PAUSE_ALL);
+}
+
+#[tokio::test]
+async fn emergency_withdraw_forfeits_rewards_accrued_so_far() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    let bob = test.staker(1_000).await;
+
+    test.stake(&alice, 1_000).await.unwrap();
+    test.stake(&bob, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    test.warp_to(START + 1_000).await;
+    let pool = test.pool_state().await;
+    let instruction =
+        reward_pool_client::emergency_withdraw(&test.pool, &pool, &alice.owner.pubkey(), &alice.stake_account);
+    assert_error(test.process(std::slice::from_ref(&instruction), &[&alice.owner]).await, ErrorCode::EmergencyNotEnabled);
+    let instructions = [reward_pool_client::set_emergency(&test.pool, &test.payer(), true), instruction];
+    test.process(&instructions, &[&alice.owner]).await.unwrap();
+
+    // Alice leaves before her lock ends, and her half of the emissions so far
+    // goes back to the pool rather than to Bob.
+    assert_eq!(test.token_balance(&alice.stake_account).await, 1_000);
+    let pool = test.pool_state().await;
+    assert_eq!(pool.total_staked, 1_000);
+    assert_eq!(pool.total_weighted_stake, 1_000);
+    assert_eq!(pool.reward_streams[0].undistributed, 500_000);
+    assert_eq!(pool.reward_streams[0].reward_liability, 500_000);
+    let user = test.user_state(&alice).await;
+    assert_eq!((user.balance_staked, user.reward_per_token_pending[0]), (0, 0));
+    assert_eq!(test.pending(&bob).await, 500_000);
+
+    test.warp_to(START + 2_000).await;
+    assert_eq!(test.pending(&bob).await, 1_500_000);
+}
+
+#[tokio::test]
+async fn emergency_withdraw_returns_the_stake_when_reward_math_fails() {
+    let mut test = TestPool::with_lock_period(0).await;
+    let alice = test.staker(1_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    // A reward rate overflowing the emissions fails every reward update.
+    test.warp_to(START + 1_000).await;
+    let mut pool = test.pool_state().await;
+    pool.reward_streams[0].reward_rate = u128::MAX;
+    test.set_pool_state(&pool).await;
+    assert_error(test.claim(&alice).await, ErrorCode::MathOverflow);
+
+    let instructions = [
+        reward_pool_client::set_emergency(&test.pool, &test.payer(), true),
+        reward_pool_client::emergency_withdraw(&test.pool, &pool, &alice.owner.pubkey(), &alice.stake_account),
+    ];
+    test.process(&instructions, &[&alice.owner]).await.unwrap();
+    assert_eq!(test.token_balance(&alice.stake_account).await, 1_000);
+    assert_eq!(test.token_balance(&test.staking_vault.clone()).await, 0);
+    let pool = test.pool_state().await;
+    assert_eq!((pool.total_staked, pool.total_weighted_stake), (0, 0));
+    assert_eq!(pool.reward_streams[0].last_update_time, START as u64);
+    assert_eq!(test.user_state(&alice).await.balance_staked, 0);
+}
+
+#[tokio::test]
+async fn early_unstake_penalty_goes_to_other_stakers() {
+    let config = PoolConfig {
+        penalty_curve: PenaltyCurve::Flat,
+        early_unstake_penalty_bps: 1_000,
+        penalty_destination: PenaltyDestination::Stakers,
+        ..config(LOCK_PERIOD)
+    };
+    let mut test = TestPool::start(LOCK_PERIOD, config, true).await;
+    let alice = test.staker(1_000).await;
+    // 255 divides `PRECISION`, so Bob's share of the penalty is exact.
+    let bob = test.staker(255).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.stake(&bob, 255).await.unwrap();
+
+    // Alice keeps half her stake, which earns nothing of the penalty she paid.
+    test.early_unstake(&alice, 500).await.unwrap();
+    assert_eq!(test.token_balance(&alice.stake_account).await, 450);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, 50);
+    assert_eq!(test.pending(&alice).await, 0);
+    assert_eq!(test.pending(&bob).await, 50);
+    let pool = test.pool_state().await;
+    assert_eq!(pool.total_weighted_stake, 755);
+    assert_eq!(pool.reward_streams[0].reward_liability, 50);
+}
+
+#[tokio::test]
+async fn switching_to_independent_lock_locks_existing_rewards() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(2_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    test.warp_to(START + 4_000).await;
+    let config = PoolConfig {
+        reward_lock_policy: RewardLockPolicy::Independent,
+        reward_lock_period: 10_000,
+        ..config(LOCK_PERIOD)
+    };
+    let instruction = reward_pool_client::update_pool_config(&test.pool, &test.payer(), None, None, Some(config));
+    test.process(&[instruction], &[]).await.unwrap();
+
+    // Alice's stake matured, but her rewards are locked from the switch on.
+    test.warp_to(START + 4_100).await;
+    assert_error(test.claim(&alice).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+    test.stake(&alice, 1_000).await.unwrap();
+    assert_eq!(test.user_state(&alice).await.reward_unlock_time, START as u64 + 14_000);
+
+    test.warp_to(START + 14_000).await;
+    test.claim(&alice).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, 14_000_000);
+    assert_eq!(test.user_state(&alice).await.reward_unlock_time, START as u64 + 24_000);
+}
+
+#[tokio::test]
+async fn excess_stake_is_swept_without_touching_rewards() {
+    let mut test = TestPool::compounding(0).await;
+    let alice = test.staker(1_000).await;
+    let destination = test.create_token_account(&test.staking_mint.clone(), &test.payer()).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    // Staking and reward tokens share a mint, so only separate vaults keep rewards out of sweeps.
+    let pool = Keypair::new();
+    let pool_signer = reward_pool_client::pool_signer_address(&pool.pubkey()).0;
+    let vault = test.create_token_account(&test.staking_mint.clone(), &pool_signer).await;
+    let treasury = test.create_token_account(&test.staking_mint.clone(), &test.payer()).await;
+    let rent = test.context.banks_client.get_rent().await.unwrap();
+    let instructions = [
+        reward_pool_client::create_pool_account(
+            &test.payer(),
+            &pool.pubkey(),
+            rent.minimum_balance(reward_pool_client::pool_account_size()),
+        ),
+        reward_pool_client::initialize_pool(
+            &InitializePoolAccounts {
+                pool: pool.pubkey(),
+                authority: test.payer(),
+                staking_mint: test.staking_mint,
+                staking_vault: vault,
+                reward_mint: test.staking_mint,
+                reward_vault: vault,
+                treasury,
+            },
+            REWARD_DURATION,
+            0,
+            config(0),
+        ),
+    ];
+    assert_error(test.process(&instructions, &[&pool]).await, anchor_lang::__private::ErrorCode::ConstraintRaw);
+    let instruction =
+        reward_pool_client::add_reward_stream(&test.pool, &test.payer(), &test.staking_mint, &test.staking_vault);
+    assert_error(test.process(&[instruction], &[]).await, anchor_lang::__private::ErrorCode::ConstraintRaw);
+
+    test.mint_to(&test.staking_mint.clone(), &test.staking_vault.clone(), 300).await;
+    let pool = test.pool_state().await;
+    let instruction = reward_pool_client::sweep_excess_stake(&test.pool, &pool, &destination);
+    test.process(std::slice::from_ref(&instruction), &[]).await.unwrap();
+    assert_eq!(test.token_balance(&destination).await, 300);
+    assert_eq!(test.token_balance(&test.staking_vault.clone()).await, 1_000);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, FUND_AMOUNT);
+    test.warp_to(START + 100).await;
+    assert_error(test.process(&[instruction], &[]).await, ErrorCode::NoExcessStake);
+}
+
+#[tokio::test]
+async fn longer_locks_earn_a_larger_share() {
+    let config = PoolConfig {
+        max_lock_period: 4 * LOCK_PERIOD,
+        max_lock_multiplier_bps: 20_000,
+        ..config(LOCK_PERIOD)
+    };
+    let mut test = TestPool::start(LOCK_PERIOD, config, false).await;
+    let alice = test.staker(1_000).await;
+    let bob = test.staker(1_000).await;
+
+    assert_error(test.stake_locked(&alice, 1_000, LOCK_PERIOD - 1).await, ErrorCode::InvalidLockDuration);
+    assert_error(test.stake_locked(&alice, 1_000, 4 * LOCK_PERIOD + 1).await, ErrorCode::InvalidLockDuration);
+    test.stake(&alice, 1_000).await.unwrap();
+    test.stake_locked(&bob, 1_000, 4 * LOCK_PERIOD).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!((pool.total_staked, pool.total_weighted_stake), (2_000, 3_000));
+    assert_eq!(test.user_state(&bob).await.balance_weighted, 2_000);
+
+    // Locking for the longest period doubles Bob's weight.
+    test.warp_to(START + 3_000).await;
+    assert_eq!(test.pending(&alice).await, 1_000_000);
+    assert_eq!(test.pending(&bob).await, 2_000_000);
+
+    // Alice's stake unlocks first, after which Bob earns everything.
+    test.warp_to(START + LOCK_PERIOD as i64).await;
+    test.unstake(&alice, 1_000).await.unwrap();
+    assert_error(test.unstake(&bob, 1_000).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+    assert_eq!(test.pool_state().await.total_weighted_stake, 2_000);
+
+    test.warp_to(START + 2 * LOCK_PERIOD as i64).await;
+    assert_eq!(test.pending(&alice).await, 1_200_000);
+    assert_eq!(test.pending(&bob).await, 6_000_000);
+}
+
+#[tokio::test]
+async fn early_unstake_penalty_decays_into_the_treasury() {
+    let config = PoolConfig {
+        penalty_curve: PenaltyCurve::LinearDecay,
+        early_unstake_penalty_bps: 1_000,
+        ..config(LOCK_PERIOD)
+    };
+    let mut test = TestPool::start(LOCK_PERIOD, config, false).await;
+    let alice = test.staker(1_000).await;
+    let treasury = test.pool_state().await.treasury;
+    test.stake(&alice, 1_000).await.unwrap();
+
+    // A tenth is forfeited at the start of the lock, decaying to nothing at its end.
+    test.warp_to(START + 900).await;
+    test.early_unstake(&alice, 400).await.unwrap();
+    assert_eq!(test.token_balance(&alice.stake_account).await, 370);
+    assert_eq!(test.token_balance(&treasury).await, 30);
+
+    test.warp_to(START + 2_700).await;
+    test.early_unstake(&alice, 400).await.unwrap();
+    assert_eq!(test.token_balance(&alice.stake_account).await, 760);
+    assert_eq!(test.token_balance(&treasury).await, 40);
+
+    test.warp_to(START + LOCK_PERIOD as i64).await;
+    test.early_unstake(&alice, 200).await.unwrap();
+    assert_eq!(test.token_balance(&alice.stake_account).await, 960);
+    assert_eq!(test.token_balance(&treasury).await, 40);
+    assert_eq!(test.token_balance(&test.staking_vault.clone()).await, 0);
+    assert_eq!(test.pool_state().await.total_staked, 0);
+}
+
+#[tokio::test]
+async fn vested_claims_release_linearly_after_the_cliff() {
+    let config = PoolConfig {
+        vesting_period: 10_000,
+        vesting_cliff: 2_000,
+        ..config(0)
+    };
+    let mut test = TestPool::start(0, config, false).await;
+    let alice = test.staker(1_000).await;
+    let instruction = reward_pool_client::create_vesting(&test.pool, &alice.owner.pubkey());
+    test.process(&[instruction], &[&alice.owner]).await.unwrap();
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    test.warp_to(START + 1_000).await;
+    assert_error(test.claim(&alice).await, ErrorCode::ClaimMustVest);
+    let instruction = reward_pool_client::claim_vesting(&test.pool, 0, &alice.owner.pubkey());
+    test.process(&[instruction], &[&alice.owner]).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!(pool.reward_streams[0].vesting_outstanding, 1_000_000);
+    assert_eq!(pool.reward_streams[0].reward_liability, 0);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, FUND_AMOUNT);
+
+    // Nothing is released before the cliff, after which it vests linearly from the claim.
+    let withdraw =
+        reward_pool_client::withdraw_vested(&test.pool, &pool, 0, &alice.owner.pubkey(), &alice.reward_account);
+    test.warp_to(START + 2_999).await;
+    assert_error(
+        test.process(std::slice::from_ref(&withdraw), &[&alice.owner]).await,
+        ErrorCode::NothingVested,
+    );
+    test.warp_to(START + 6_000).await;
+    test.process(std::slice::from_ref(&withdraw), &[&alice.owner]).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, 500_000);
+
+    test.warp_to(START + 11_000).await;
+    test.process(&[withdraw], &[&alice.owner]).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, 1_000_000);
+    assert_eq!(test.pool_state().await.reward_streams[0].vesting_outstanding, 0);
+}
+
+#[tokio::test]
+async fn compounded_rewards_are_staked_under_a_new_lock() {
+    let mut test = TestPool::compounding(LOCK_PERIOD).await;
+    let alice = test.staker(1_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    test.warp_to(START + 100).await;
+    test.compound(&alice).await.unwrap();
+    let user = test.user_state(&alice).await;
+    assert_eq!(user.balance_staked, 101_000);
+    assert_eq!(user.reward_per_token_pending[0], 0);
+    assert_eq!(
+        (user.positions[1].amount, user.positions[1].unlock_time),
+        (100_000, START as u64 + 100 + LOCK_PERIOD)
+    );
+    let pool = test.pool_state().await;
+    assert_eq!(pool.total_staked, 101_000);
+    assert_eq!(pool.reward_streams[0].reward_liability, 0);
+    assert_eq!(test.token_balance(&test.staking_vault.clone()).await, 101_000);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, FUND_AMOUNT - 100_000);
+
+    // The first deposit keeps its lock, while the compounded rewards wait for theirs.
+    test.warp_to(START + LOCK_PERIOD as i64).await;
+    assert_error(test.unstake(&alice, 1_001).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+    test.unstake(&alice, 1_000).await.unwrap();
+    test.warp_to(START + 100 + LOCK_PERIOD as i64).await;
+    test.unstake(&alice, 100_000).await.unwrap();
+    assert_eq!(test.token_balance(&alice.stake_account).await, 101_000);
+}
+
+#[tokio::test]
+async fn authority_moves_once_the_new_key_accepts() {
+    let mut test = TestPool::new().await;
+    let authority = test.payer();
+    let new_authority = Keypair::new();
+    let stranger = Keypair::new();
+
+    let instruction = reward_pool_client::propose_authority(&test.pool, &authority, new_authority.pubkey());
+    test.process(&[instruction], &[]).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!((pool.authority, pool.pending_authority), (authority, new_authority.pubkey()));
+
+    let instruction = reward_pool_client::accept_authority(&test.pool, &stranger.pubkey());
+    assert_error(
+        test.process(&[instruction], &[&stranger]).await,
+        anchor_lang::__private::ErrorCode::ConstraintHasOne,
+    );
+    let instruction = reward_pool_client::accept_authority(&test.pool, &new_authority.pubkey());
+    test.process(&[instruction], &[&new_authority]).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!((pool.authority, pool.pending_authority), (new_authority.pubkey(), Pubkey::default()));
+
+    // Only the new authority administers the pool from now on.
+    let instruction = reward_pool_client::set_emergency(&test.pool, &authority, true);
+    assert_error(test.process(&[instruction], &[]).await, anchor_lang::__private::ErrorCode::ConstraintHasOne);
+    let instruction = reward_pool_client::set_emergency(&test.pool, &new_authority.pubkey(), true);
+    test.process(&[instruction], &[&new_authority]).await.unwrap();
+    assert!(test.pool_state().await.emergency);
+}
+
+#[tokio::test]
+async fn scheduled_funding_emits_from_its_start() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+
+    let pool = test.pool_state().await;
+    let schedule = FundSchedule {
+        stream_index: 0,
+        amount: FUND_AMOUNT,
+        start_ts: START as u64 + 10_000,
+        end_ts: START as u64 + 10_000 + REWARD_DURATION,
+    };
+    let instruction =
+        reward_pool_client::fund_scheduled(&test.pool, &pool, &test.payer(), &test.funding_account, &schedule);
+    test.process(&[instruction], &[]).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!(pool.reward_streams[0].reward_rate, 1_000 * PRECISION);
+    assert_eq!(pool.reward_streams[0].reward_start_time, schedule.start_ts);
+    assert_eq!(pool.reward_streams[0].reward_duration_end, schedule.end_ts);
+
+    // Nothing accrues before the campaign starts.
+    test.warp_to(START + 9_000).await;
+    assert_eq!(test.pending(&alice).await, 0);
+    test.warp_to(START + 12_000).await;
+    assert_eq!(test.pending(&alice).await, 2_000_000);
+
+    test.warp_to(START + 20_000 + REWARD_DURATION as i64).await;
+    test.claim(&alice).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, FUND_AMOUNT);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, 0);
+}
+
+#[tokio::test]
+async fn new_reward_duration_rerates_the_remaining_rewards() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    test.warp_to(START + 43_200).await;
+    let instruction =
+        reward_pool_client::update_pool_config(&test.pool, &test.payer(), Some(2 * REWARD_DURATION), None, None);
+    test.process(&[instruction], &[]).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!(pool.reward_duration, 2 * REWARD_DURATION);
+    assert_eq!(pool.reward_streams[0].reward_per_token_stored, 43_200 * PRECISION);
+    assert_eq!(pool.reward_streams[0].reward_rate, 250 * PRECISION);
+    assert_eq!(pool.reward_streams[0].reward_duration_end, START as u64 + 43_200 + 2 * REWARD_DURATION);
+
+    // The remaining half now emits at a quarter of the rate, over twice as long.
+    test.warp_to(START + 43_200 + REWARD_DURATION as i64).await;
+    assert_eq!(test.pending(&alice).await, 64_800_000);
+    test.warp_to(START + 43_200 + 2 * REWARD_DURATION as i64).await;
+    test.claim(&alice).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, FUND_AMOUNT);
+}
+
+#[tokio::test]
+async fn view_pending_reports_without_changing_state() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+    test.warp_to(START + 1_000).await;
+
+    let user = reward_pool_client::user_address(&alice.owner.pubkey(), &test.pool).0;
+    let pool_before = test.context.banks_client.get_account(test.pool).await.unwrap().unwrap();
+    let user_before = test.context.banks_client.get_account(user).await.unwrap().unwrap();
+    let instruction = reward_pool_client::view_pending(&test.pool, &user);
+    test.process(&[instruction], &[]).await.unwrap();
+
+    let pending = return_data()
+        .iter()
+        .rev()
+        .find_map(|data| PendingRewards::try_from_slice(data).ok().filter(|x| x.user == user))
+        .unwrap();
+    assert_eq!(pending.pending[0], 1_000_000);
+    assert_eq!(pending.pending[0], test.pending(&alice).await);
+    assert_eq!(pending.reward_rate[0], 1_000 * PRECISION);
+    assert_eq!((pending.balance_staked, pending.total_staked), (1_000, 1_000));
+    assert_eq!(pending.claim_unlock_time, START as u64 + LOCK_PERIOD);
+    assert_eq!(pending.timestamp, START as u64 + 1_000);
+    let pool_after = test.context.banks_client.get_account(test.pool).await.unwrap().unwrap();
+    let user_after = test.context.banks_client.get_account(user).await.unwrap().unwrap();
+    assert_eq!((pool_before.data, user_before.data), (pool_after.data, user_after.data));
+}
+
+#[tokio::test]
+async fn undistributed_rewards_are_recovered_by_the_authority() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    let destination = test.create_token_account(&test.reward_mint.clone(), &test.payer()).await;
+
+    // Half the period passes with nobody staked.
+    test.fund(FUND_AMOUNT).await.unwrap();
+    test.warp_to(START + 43_200).await;
+    test.stake(&alice, 1_000).await.unwrap();
+
+    let pool = test.pool_state().await;
+    let instruction = reward_pool_client::recover_undistributed(&test.pool, &pool, 0, &destination);
+    test.process(std::slice::from_ref(&instruction), &[]).await.unwrap();
+    assert_eq!(test.token_balance(&destination).await, 43_200_000);
+    assert_eq!(test.pool_state().await.reward_streams[0].undistributed, 0);
+    test.warp_to(START + 43_300).await;
+    assert_error(test.process(&[instruction], &[]).await, ErrorCode::AmountMustBeGreaterThanZero);
+
+    // Stakers are still paid in full.
+    test.warp_to(START + REWARD_DURATION as i64).await;
+    test.claim(&alice).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, 43_200_000);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, 0);
+}
+
+#[tokio::test]
+async fn pool_closes_once_its_users_left_and_rewards_ended() {
+    let mut test = TestPool::with_lock_period(0).await;
+    let alice = test.staker(1_000).await;
+    let staking_refundee = test.create_token_account(&test.staking_mint.clone(), &test.payer()).await;
+    let reward_refundee = test.create_token_account(&test.reward_mint.clone(), &test.payer()).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    let pool = test.pool_state().await;
+    let refundees = ClosePoolRefundees {
+        refundee: test.payer(),
+        staking_refundee,
+        reward_refundee,
+        extra_reward_refundees: Vec::new(),
+    };
+    let close = reward_pool_client::close_pool(&test.pool, &pool, &refundees);
+    // Deposits, funding and new users must be paused, and every user gone.
+    assert_error(
+        test.process(std::slice::from_ref(&close), &[]).await,
+        anchor_lang::__private::ErrorCode::ConstraintRaw,
+    );
+    let instruction =
+        reward_pool_client::pause(&test.pool, &test.payer(), PAUSE_DEPOSITS | PAUSE_FUNDING | PAUSE_NEW_USERS);
+    test.process(&[instruction], &[]).await.unwrap();
+
+    test.warp_to(START + 1_000).await;
+    assert_error(
+        test.process(std::slice::from_ref(&close), &[]).await,
+        anchor_lang::__private::ErrorCode::ConstraintRaw,
+    );
+    test.claim(&alice).await.unwrap();
+    test.unstake(&alice, 1_000).await.unwrap();
+    let instruction = reward_pool_client::close_user(&test.pool, &alice.owner.pubkey());
+    test.process(&[instruction], &[&alice.owner]).await.unwrap();
+    assert_eq!(test.pool_state().await.user_stake_count, 0);
+
+    test.warp_to(START + 2_000).await;
+    assert_error(test.process(std::slice::from_ref(&close), &[]).await, ErrorCode::RewardPeriodActive);
+
+    // What nobody earned goes back with the vaults.
+    test.warp_to(START + REWARD_DURATION as i64 + 1).await;
+    test.process(&[close], &[]).await.unwrap();
+    assert!(test.context.banks_client.get_account(test.pool).await.unwrap().is_none());
+    assert!(test.context.banks_client.get_account(test.reward_vault).await.unwrap().is_none());
+    assert_eq!(test.token_balance(&alice.reward_account).await, 1_000_000);
+    assert_eq!(test.token_balance(&reward_refundee).await, FUND_AMOUNT - 1_000_000);
+    assert_eq!(test.token_balance(&staking_refundee).await, 0);
+}
+
+#[tokio::test]
+async fn top_ups_lock_only_the_new_deposit() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(2_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    // Topping up halfway through the lock leaves the first deposit's unlock time alone.
+    test.warp_to(START + 1_800).await;
+    test.stake(&alice, 500).await.unwrap();
+    let user = test.user_state(&alice).await;
+    assert_eq!((user.positions[0].amount, user.positions[0].unlock_time), (1_000, START as u64 + LOCK_PERIOD));
+    assert_eq!(
+        (user.positions[1].amount, user.positions[1].unlock_time),
+        (500, START as u64 + 1_800 + LOCK_PERIOD)
+    );
+
+    // Matured deposits are withdrawn oldest first, while rewards stay locked with the top-up.
+    test.warp_to(START + LOCK_PERIOD as i64).await;
+    assert_error(test.unstake(&alice, 1_001).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+    assert_error(test.claim(&alice).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+    test.unstake(&alice, 600).await.unwrap();
+    let user = test.user_state(&alice).await;
+    assert_eq!((user.positions[0].amount, user.positions[0].unlock_time), (400, START as u64 + LOCK_PERIOD));
+    assert_eq!(user.positions[1].amount, 500);
+
+    test.warp_to(START + 1_800 + LOCK_PERIOD as i64).await;
+    test.claim(&alice).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, 1_000 * (1_800 + LOCK_PERIOD));
+    test.unstake(&alice, 900).await.unwrap();
+    let user = test.user_state(&alice).await;
+    assert_eq!((user.balance_staked, user.positions[0].amount), (0, 0));
+    assert_eq!(test.token_balance(&alice.stake_account).await, 2_000);
+}
+
+#[tokio::test]
+async fn deposits_are_limited_to_the_open_positions() {
+    let config = PoolConfig {
+        max_lock_period: LOCK_PERIOD + MAX_STAKE_POSITIONS as u64,
+        ..config(LOCK_PERIOD)
+    };
+    let mut test = TestPool::start(LOCK_PERIOD, config, false).await;
+    let alice = test.staker(100).await;
+
+    let pool = test.pool_state().await;
+    let stake = |lock_duration| {
+        reward_pool_client::stake(&test.pool, &pool, &alice.owner.pubkey(), &alice.stake_account, 1, lock_duration)
+    };
+    let merged = [stake(LOCK_PERIOD), stake(LOCK_PERIOD)];
+    let separate: Vec<_> = (1..MAX_STAKE_POSITIONS as u64).map(|i| stake(LOCK_PERIOD + i)).collect();
+    let overflowing = stake(LOCK_PERIOD + MAX_STAKE_POSITIONS as u64);
+
+    // Deposits made and unlocking together share a position.
+    test.process(&merged, &[&alice.owner]).await.unwrap();
+    assert_eq!(test.user_state(&alice).await.positions[0].amount, 2);
+    for instruction in separate {
+        test.process(&[instruction], &[&alice.owner]).await.unwrap();
+    }
+    assert_error(test.process(&[overflowing], &[&alice.owner]).await, ErrorCode::MaxStakePositions);
+    let user = test.user_state(&alice).await;
+    assert_eq!(user.balance_staked, 17);
+    assert_eq!(user.positions[MAX_STAKE_POSITIONS - 1].unlock_time, START as u64 + LOCK_PERIOD + 15);
+}
+
+#[tokio::test]
+async fn matured_positions_merge_to_make_room() {
+    let config = PoolConfig {
+        max_lock_period: LOCK_PERIOD + MAX_STAKE_POSITIONS as u64,
+        ..config(LOCK_PERIOD)
+    };
+    let mut test = TestPool::start(LOCK_PERIOD, config, false).await;
+    let alice = test.staker(100).await;
+    for i in 0..MAX_STAKE_POSITIONS as u64 {
+        test.stake_locked(&alice, 1, LOCK_PERIOD + i).await.unwrap();
+    }
+
+    // The four deposits matured by now fold into one, freeing the slots for new ones.
+    test.warp_to(START + LOCK_PERIOD as i64 + 3).await;
+    test.stake(&alice, 10).await.unwrap();
+    let user = test.user_state(&alice).await;
+    assert_eq!(user.balance_staked, 26);
+    assert_eq!(
+        (user.positions[0].amount, user.positions[0].weight, user.positions[0].unlock_time),
+        (4, 4, START as u64 + LOCK_PERIOD + 3)
+    );
+    assert_eq!(user.positions[1].unlock_time, START as u64 + LOCK_PERIOD + 4);
+    let newest = &user.positions[MAX_STAKE_POSITIONS - 3];
+    assert_eq!((newest.amount, newest.unlock_time), (10, START as u64 + 2 * LOCK_PERIOD + 3));
+    assert_eq!(user.positions[MAX_STAKE_POSITIONS - 2].amount, 0);
+
+    // Merged stake stays withdrawable, and only it.
+    assert_error(test.unstake(&alice, 5).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+    test.unstake(&alice, 4).await.unwrap();
+    assert_eq!(test.user_state(&alice).await.positions[0].unlock_time, START as u64 + LOCK_PERIOD + 4);
+}
+
+#[tokio::test]
+async fn early_unstake_needs_a_penalty_curve_it_can_route() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    assert_error(test.early_unstake(&alice, 1_000).await, ErrorCode::EarlyUnstakeDisabled);
+
+    // Penalties only go to stakers when they are paid in the reward token, and
+    // can't exceed the amount withdrawn.
+    let stakers = PoolConfig {
+        penalty_curve: PenaltyCurve::Flat,
+        early_unstake_penalty_bps: 1_000,
+        penalty_destination: PenaltyDestination::Stakers,
+        ..config(LOCK_PERIOD)
+    };
+    let excessive = PoolConfig {
+        penalty_curve: PenaltyCurve::Flat,
+        early_unstake_penalty_bps: 10_001,
+        ..config(LOCK_PERIOD)
+    };
+    for config in [stakers, excessive] {
+        let instruction = reward_pool_client::update_pool_config(&test.pool, &test.payer(), None, None, Some(config));
+        assert_error(test.process(&[instruction], &[]).await, ErrorCode::InvalidPenaltyConfig);
+    }
+}
+
+#[tokio::test]
+async fn early_unstake_withdraws_matured_stake_without_penalty() {
+    let config = PoolConfig {
+        penalty_curve: PenaltyCurve::Flat,
+        early_unstake_penalty_bps: 1_000,
+        penalty_destination: PenaltyDestination::Stakers,
+        ..config(LOCK_PERIOD)
+    };
+    let mut test = TestPool::start(LOCK_PERIOD, config, true).await;
+    let alice = test.staker(2_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.warp_to(START + 1_800).await;
+    test.stake(&alice, 1_000).await.unwrap();
+
+    // Only the part taken from the locked deposit is penalised.
+    test.warp_to(START + LOCK_PERIOD as i64).await;
+    test.early_unstake(&alice, 1_500).await.unwrap();
+    assert_eq!(test.token_balance(&alice.stake_account).await, 1_450);
+    let user = test.user_state(&alice).await;
+    assert_eq!((user.positions[0].amount, user.positions[0].unlock_time), (500, START as u64 + 1_800 + LOCK_PERIOD));
+
+    // With nobody else staked the penalty waits in the reward vault for the next funding.
+    let pool = test.pool_state().await;
+    assert_eq!(pool.reward_streams[0].undistributed, 50);
+    assert_eq!(pool.reward_streams[0].reward_liability, 0);
+    assert_eq!(pool.total_staked, 500);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, 50);
+}
+
+#[tokio::test]
+async fn rewards_of_anytime_pools_are_claimable_during_the_lock() {
+    let config = PoolConfig {
+        reward_lock_policy: RewardLockPolicy::Anytime,
+        ..config(LOCK_PERIOD)
+    };
+    let mut test = TestPool::start(LOCK_PERIOD, config, false).await;
+    let alice = test.staker(1_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    // Only the principal stays locked.
+    test.warp_to(START + 1_800).await;
+    assert_error(test.unstake(&alice, 1_000).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+    test.claim(&alice).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, 1_800_000);
+    assert_eq!(test.user_state(&alice).await.reward_unlock_time, 0);
+}
+
+#[tokio::test]
+async fn independent_reward_lock_restarts_with_each_claim() {
+    let config = PoolConfig {
+        reward_lock_policy: RewardLockPolicy::Independent,
+        reward_lock_period: 10_000,
+        ..config(LOCK_PERIOD)
+    };
+    let mut test = TestPool::start(LOCK_PERIOD, config, false).await;
+    assert_eq!(test.pool_state().await.reward_lock_start, START as u64);
+    let alice = test.staker(2_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+    assert_eq!(test.user_state(&alice).await.reward_unlock_time, START as u64 + 10_000);
+
+    // The principal unlocks with its deposit, the rewards only after their own period.
+    test.warp_to(START + LOCK_PERIOD as i64).await;
+    test.unstake(&alice, 1_000).await.unwrap();
+    test.warp_to(START + 5_000).await;
+    assert_error(test.claim(&alice).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+
+    test.warp_to(START + 10_000).await;
+    test.claim(&alice).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, 3_600_000);
+    assert_eq!(test.user_state(&alice).await.reward_unlock_time, START as u64 + 20_000);
+
+    // Rewards earned after a claim wait out a full period again.
+    test.stake(&alice, 1_000).await.unwrap();
+    test.warp_to(START + 15_000).await;
+    assert_error(test.claim(&alice).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+    test.warp_to(START + 20_000).await;
+    test.claim(&alice).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, 13_600_000);
+}
+
+#[tokio::test]
+async fn only_staking_mint_rewards_compound() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    test.warp_to(START + 100).await;
+    assert_error(test.compound(&alice).await, ErrorCode::CompoundRequiresStakingMint);
+}
+
+#[tokio::test]
+async fn compounding_without_a_new_lock_waits_for_the_claim_lock() {
+    let config = PoolConfig {
+        compound_resets_lock: false,
+        ..config(LOCK_PERIOD)
+    };
+    let mut test = TestPool::start(LOCK_PERIOD, config, true).await;
+    let alice = test.staker(1_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    test.warp_to(START + 100).await;
+    assert_error(test.compound(&alice).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+
+    // Compounded rewards can be withdrawn right away, like claimed ones.
+    test.warp_to(START + LOCK_PERIOD as i64).await;
+    test.compound(&alice).await.unwrap();
+    let user = test.user_state(&alice).await;
+    assert_eq!(
+        (user.positions[1].amount, user.positions[1].unlock_time),
+        (3_600_000, START as u64 + LOCK_PERIOD)
+    );
+    test.unstake(&alice, 3_601_000).await.unwrap();
+    assert_eq!(test.token_balance(&alice.stake_account).await, 3_601_000);
+    assert_eq!(test.pool_state().await.total_staked, 0);
+}
+
+#[tokio::test]
+async fn claims_only_vest_in_vesting_pools() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    let instruction = reward_pool_client::create_vesting(&test.pool, &alice.owner.pubkey());
+    test.process(&[instruction], &[&alice.owner]).await.unwrap();
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    test.warp_to(START + LOCK_PERIOD as i64).await;
+    let instruction = reward_pool_client::claim_vesting(&test.pool, 0, &alice.owner.pubkey());
+    assert_error(test.process(&[instruction], &[&alice.owner]).await, ErrorCode::VestingDisabled);
+}
+
+#[tokio::test]
+async fn each_vested_claim_keeps_its_own_schedule() {
+    let config = PoolConfig {
+        vesting_period: 10_000,
+        vesting_cliff: 2_000,
+        ..config(0)
+    };
+    let mut test = TestPool::start(0, config, false).await;
+    let alice = test.staker(1_000).await;
+    let instruction = reward_pool_client::create_vesting(&test.pool, &alice.owner.pubkey());
+    test.process(&[instruction], &[&alice.owner]).await.unwrap();
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+
+    let claim = reward_pool_client::claim_vesting(&test.pool, 0, &alice.owner.pubkey());
+    test.warp_to(START + 1_000).await;
+    test.process(std::slice::from_ref(&claim), &[&alice.owner]).await.unwrap();
+    test.warp_to(START + 3_000).await;
+    test.process(&[claim], &[&alice.owner]).await.unwrap();
+    assert_eq!(test.pool_state().await.reward_streams[0].vesting_outstanding, 3_000_000);
+
+    // The first claim is 40% vested, the second has just passed its cliff and is 20% vested.
+    let pool = test.pool_state().await;
+    let withdraw =
+        reward_pool_client::withdraw_vested(&test.pool, &pool, 0, &alice.owner.pubkey(), &alice.reward_account);
+    let close = reward_pool_client::close_vesting(&test.pool, &alice.owner.pubkey());
+    test.warp_to(START + 5_000).await;
+    test.process(std::slice::from_ref(&withdraw), &[&alice.owner]).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, 800_000);
+    assert_error(
+        test.process(std::slice::from_ref(&close), &[&alice.owner]).await,
+        anchor_lang::__private::ErrorCode::ConstraintRaw,
+    );
+
+    // Once everything is withdrawn the vesting account can be closed.
+    test.warp_to(START + 13_000).await;
+    test.process(&[withdraw], &[&alice.owner]).await.unwrap();
+    assert_eq!(test.token_balance(&alice.reward_account).await, 3_000_000);
+    assert_eq!(test.pool_state().await.reward_streams[0].vesting_outstanding, 0);
+    test.process(&[close], &[&alice.owner]).await.unwrap();
+    let vesting = reward_pool_client::vesting_address(&alice.owner.pubkey(), &test.pool).0;
+    assert!(test.context.banks_client.get_account(vesting).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn lock_multiplier_interpolates_and_unstaking_removes_its_share() {
+    let config = PoolConfig {
+        max_lock_period: 4 * LOCK_PERIOD,
+        max_lock_multiplier_bps: 20_000,
+        ..config(LOCK_PERIOD)
+    };
+    let mut test = TestPool::start(LOCK_PERIOD, config, false).await;
+    let alice = test.staker(1_000).await;
+
+    // Halfway between the minimum and maximum lock earns half the extra weight.
+    let lock_duration = LOCK_PERIOD + 3 * LOCK_PERIOD / 2;
+    test.stake_locked(&alice, 1_000, lock_duration).await.unwrap();
+    let user = test.user_state(&alice).await;
+    assert_eq!((user.balance_weighted, user.positions[0].weight), (1_500, 1_500));
+
+    test.warp_to(START + lock_duration as i64).await;
+    test.unstake(&alice, 400).await.unwrap();
+    let user = test.user_state(&alice).await;
+    assert_eq!((user.balance_staked, user.balance_weighted), (600, 900));
+    assert_eq!((user.positions[0].amount, user.positions[0].weight), (600, 900));
+    let pool = test.pool_state().await;
+    assert_eq!((pool.total_staked, pool.total_weighted_stake), (600, 900));
+}
+
+#[tokio::test]
+async fn lock_config_must_cover_the_lock_period() {
+    let mut test = TestPool::new().await;
+    let short = PoolConfig {
+        max_lock_period: LOCK_PERIOD - 1,
+        ..config(LOCK_PERIOD)
+    };
+    let discounted = PoolConfig {
+        max_lock_period: 2 * LOCK_PERIOD,
+        max_lock_multiplier_bps: 9_999,
+        ..config(LOCK_PERIOD)
+    };
+    for config in [short, discounted] {
+        let instruction = reward_pool_client::update_pool_config(&test.pool, &test.payer(), None, None, Some(config));
+        assert_error(test.process(&[instruction], &[]).await, ErrorCode::InvalidLockConfig);
+    }
+}
+
+#[tokio::test]
+async fn scheduled_funding_rejects_invalid_schedules() {
+    let mut test = TestPool::new().await;
+    let start = START as u64 + 10_000;
+    let schedules = [
+        (start - 10_001, start - 10_001 + REWARD_DURATION, ErrorCode::InvalidRewardSchedule),
+        (start, start, ErrorCode::InvalidRewardSchedule),
+        (start, start + REWARD_DURATION - 1, ErrorCode::DurationTooShort),
+    ];
+    for (start_ts, end_ts, err) in schedules {
+        let pool = test.pool_state().await;
+        let schedule = FundSchedule { stream_index: 0, amount: FUND_AMOUNT, start_ts, end_ts };
+        let instruction =
+            reward_pool_client::fund_scheduled(&test.pool, &pool, &test.payer(), &test.funding_account, &schedule);
+        assert_error(test.process(&[instruction], &[]).await, err);
+    }
+
+    // A running period can't be replaced by a schedule.
+    test.fund(FUND_AMOUNT).await.unwrap();
+    let pool = test.pool_state().await;
+    let schedule = FundSchedule {
+        stream_index: 0,
+        amount: FUND_AMOUNT,
+        start_ts: start,
+        end_ts: start + REWARD_DURATION,
+    };
+    let instruction =
+        reward_pool_client::fund_scheduled(&test.pool, &pool, &test.payer(), &test.funding_account, &schedule);
+    assert_error(test.process(&[instruction], &[]).await, ErrorCode::RewardPeriodActive);
+}
+
+#[tokio::test]
+async fn fundings_smaller_than_the_duration_still_emit() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+
+    // Less than a token a second, which a whole-token rate would round to zero.
+    test.fund(1_000).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!(pool.reward_streams[0].reward_rate, 1_000 * PRECISION / REWARD_DURATION as u128);
+
+    test.warp_to(START + 43_200).await;
+    assert_eq!(test.pending(&alice).await, 499);
+    test.warp_to(START + REWARD_DURATION as i64).await;
+    test.claim(&alice).await.unwrap();
+    // Only the flooring of the final settlement stays behind.
+    assert_eq!(test.token_balance(&alice.reward_account).await, 999);
+    assert_eq!(test.token_balance(&test.reward_vault.clone()).await, 1);
+    assert_eq!(test.pool_state().await.reward_streams[0].reward_liability, 0);
+}
+
+#[tokio::test]
+async fn authority_transfer_can_be_cancelled_before_acceptance() {
+    let mut test = TestPool::new().await;
+    let authority = test.payer();
+    let new_authority = Keypair::new();
+    let stranger = Keypair::new();
+
+    for proposed in [Pubkey::default(), authority] {
+        let instruction = reward_pool_client::propose_authority(&test.pool, &authority, proposed);
+        assert_error(test.process(&[instruction], &[]).await, ErrorCode::InvalidNewAuthority);
+    }
+    let instruction = reward_pool_client::cancel_authority_transfer(&test.pool, &authority);
+    assert_error(test.process(&[instruction], &[]).await, ErrorCode::NoPendingAuthority);
+    let instruction = reward_pool_client::propose_authority(&test.pool, &stranger.pubkey(), stranger.pubkey());
+    assert_error(
+        test.process(&[instruction], &[&stranger]).await,
+        anchor_lang::__private::ErrorCode::ConstraintHasOne,
+    );
+
+    // A cancelled proposal can no longer be accepted.
+    let instruction = reward_pool_client::propose_authority(&test.pool, &authority, new_authority.pubkey());
+    test.process(&[instruction], &[]).await.unwrap();
+    // A new blockhash, so the cancellation isn't taken for the rejected one above.
+    test.warp_to(START + 1).await;
+    let instruction = reward_pool_client::cancel_authority_transfer(&test.pool, &authority);
+    test.process(&[instruction], &[]).await.unwrap();
+    let pool = test.pool_state().await;
+    assert_eq!((pool.authority, pool.pending_authority), (authority, Pubkey::default()));
+    let instruction = reward_pool_client::accept_authority(&test.pool, &new_authority.pubkey());
+    assert_error(
+        test.process(&[instruction], &[&new_authority]).await,
+        anchor_lang::__private::ErrorCode::ConstraintHasOne,
+    );
+}
+
+#[tokio::test]
+async fn pool_config_updates_are_validated() {
+    let mut test = TestPool::new().await;
+    let authority = test.payer();
+    let stranger = Keypair::new();
+
+    let instruction =
+        reward_pool_client::update_pool_config(&test.pool, &authority, Some(REWARD_DURATION - 1), None, None);
+    assert_error(test.process(&[instruction], &[]).await, ErrorCode::DurationTooShort);
+    let config = PoolConfig {
+        vesting_period: 1_000,
+        vesting_cliff: 1_001,
+        ..config(LOCK_PERIOD)
+    };
+    let instruction = reward_pool_client::update_pool_config(&test.pool, &authority, None, None, Some(config));
+    assert_error(test.process(&[instruction], &[]).await, ErrorCode::InvalidVestingConfig);
+    let instruction =
+        reward_pool_client::update_pool_config(&test.pool, &stranger.pubkey(), Some(2 * REWARD_DURATION), None, None);
+    assert_error(
+        test.process(&[instruction], &[&stranger]).await,
+        anchor_lang::__private::ErrorCode::ConstraintHasOne,
+    );
+    assert_eq!(test.pool_state().await.reward_duration, REWARD_DURATION);
+}
+
+#[tokio::test]
+async fn new_lock_period_applies_to_later_deposits() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    let bob = test.staker(1_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+
+    let instruction = reward_pool_client::update_pool_config(
+        &test.pool,
+        &test.payer(),
+        None,
+        Some(2 * LOCK_PERIOD),
+        Some(config(2 * LOCK_PERIOD)),
+    );
+    test.process(&[instruction], &[]).await.unwrap();
+    assert_eq!(test.pool_state().await.lock_period, 2 * LOCK_PERIOD);
+    test.stake(&bob, 1_000).await.unwrap();
+
+    test.warp_to(START + LOCK_PERIOD as i64).await;
+    test.unstake(&alice, 1_000).await.unwrap();
+    assert_error(test.unstake(&bob, 1_000).await, ErrorCode::CannotStakeOrClaimBeforeMaturity);
+    test.warp_to(START + 2 * LOCK_PERIOD as i64).await;
+    test.unstake(&bob, 1_000).await.unwrap();
+}
+
+#[tokio::test]
+async fn overflowing_totals_fail_with_math_errors() {
+    // Math errors keep their codes for clients telling them apart from constraint failures.
+    let codes = [
+        (ErrorCode::MathOverflow, 333),
+        (ErrorCode::MathUnderflow, 334),
+        (ErrorCode::InvalidTimestamp, 335),
+        (ErrorCode::ConversionFailed, 336),
+    ];
+    for (err, code) in codes {
+        assert_eq!(error_code(err), code);
+    }
+
+    let mut test = TestPool::new().await;
+    let alice = test.staker(2_000).await;
+    test.stake(&alice, 1_000).await.unwrap();
+
+    let mut pool = test.pool_state().await;
+    pool.total_staked = u64::MAX;
+    test.set_pool_state(&pool).await;
+    assert_error(test.stake(&alice, 500).await, ErrorCode::MathOverflow);
+
+    test.warp_to(START + LOCK_PERIOD as i64).await;
+    pool.total_staked = 999;
+    test.set_pool_state(&pool).await;
+    assert_error(test.unstake(&alice, 1_000).await, ErrorCode::MathUnderflow);
+}
+
+#[tokio::test]
+async fn state_changes_are_reported_as_events() {
+    record_events();
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    let user = reward_pool_client::user_address(&alice.owner.pubkey(), &test.pool).0;
+    test.stake(&alice, 1_000).await.unwrap();
+    test.fund(FUND_AMOUNT).await.unwrap();
+    test.warp_to(START + LOCK_PERIOD as i64).await;
+    test.claim(&alice).await.unwrap();
+
+    let staked: Vec<_> = events::<Staked>().into_iter().filter(|x| x.pool == test.pool).collect();
+    assert_eq!(staked.len(), 1);
+    assert_eq!((staked[0].user, staked[0].owner), (user, alice.owner.pubkey()));
+    assert_eq!((staked[0].amount, staked[0].weight), (1_000, 1_000));
+    assert_eq!(staked[0].unlock_time, START as u64 + LOCK_PERIOD);
+    assert_eq!((staked[0].balance_staked, staked[0].total_staked), (1_000, 1_000));
+    assert_eq!(staked[0].timestamp, START as u64);
+
+    let funded: Vec<_> = events::<PoolFunded>().into_iter().filter(|x| x.pool == test.pool).collect();
+    assert_eq!(funded.len(), 1);
+    assert_eq!((funded[0].funder, funded[0].stream_index, funded[0].amount), (test.payer(), 0, FUND_AMOUNT));
+    assert_eq!(funded[0].reward_rate, 1_000 * PRECISION);
+    assert_eq!(funded[0].reward_per_token_stored, 0);
+    assert_eq!(
+        (funded[0].reward_start_time, funded[0].reward_duration_end),
+        (START as u64, START as u64 + REWARD_DURATION)
+    );
+
+    let paid: Vec<_> = events::<RewardPaid>().into_iter().filter(|x| x.pool == test.pool).collect();
+    assert_eq!(paid.len(), 1);
+    assert_eq!((paid[0].user, paid[0].owner, paid[0].stream_index), (user, alice.owner.pubkey(), 0));
+    assert_eq!((paid[0].amount, paid[0].pending), (3_600_000, 0));
+    assert_eq!(paid[0].reward_per_token_stored, 3_600 * PRECISION);
+    assert_eq!(paid[0].timestamp, START as u64 + LOCK_PERIOD);
+}
+
+#[tokio::test]
+async fn view_pending_reports_both_unlock_times() {
+    let config = PoolConfig {
+        reward_lock_policy: RewardLockPolicy::Independent,
+        reward_lock_period: 10_000,
+        ..config(LOCK_PERIOD)
+    };
+    let mut test = TestPool::start(LOCK_PERIOD, config, false).await;
+    let alice = test.staker(1_000).await;
+    test.warp_to(START + 100).await;
+    test.stake(&alice, 1_000).await.unwrap();
+
+    test.warp_to(START + 200).await;
+    let user = reward_pool_client::user_address(&alice.owner.pubkey(), &test.pool).0;
+    let instruction = reward_pool_client::view_pending(&test.pool, &user);
+    test.process(&[instruction], &[]).await.unwrap();
+    let pending = return_data()
+        .iter()
+        .rev()
+        .find_map(|data| PendingRewards::try_from_slice(data).ok().filter(|x| x.user == user))
+        .unwrap();
+    assert_eq!(pending.stake_unlock_time, START as u64 + 100 + LOCK_PERIOD);
+    assert_eq!(pending.claim_unlock_time, START as u64 + 10_100);
+
+    // Users are only reported against their own pool.
+    let other_pool = Pubkey::new_unique();
+    let account = test.context.banks_client.get_account(test.pool).await.unwrap().unwrap();
+    test.context.set_account(&other_pool, &AccountSharedData::from(account));
+    let instruction = reward_pool_client::view_pending(&other_pool, &user);
+    assert_error(test.process(&[instruction], &[]).await, anchor_lang::__private::ErrorCode::ConstraintHasOne);
+}
+
+/// A pool as written by the program before it paid several reward tokens.
+#[derive(AnchorSerialize)]
+struct SingleRewardPool {
+    authority: Pubkey,
+    nonce: u8,
+    paused: bool,
+    staking_mint: Pubkey,
+    staking_vault: Pubkey,
+    reward_mint: Pubkey,
+    reward_vault: Pubkey,
+    reward_duration: u64,
+    reward_duration_end: u64,
+    lock_period: u64,
+    last_update_time: u64,
+    reward_rate: u64,
+    reward_per_token_stored: u128,
+    user_stake_count: u32,
+    funders: [Pubkey; 5],
+}
+
+#[tokio::test]
+async fn pools_of_the_single_reward_layout_are_rejected() {
+    let mut test = TestPool::new().await;
+    let alice = test.staker(1_000).await;
+    let pool = test.pool_state().await;
+
+    let legacy = SingleRewardPool {
+        authority: pool.authority,
+        nonce: pool.nonce,
+        paused: false,
+        staking_mint: pool.staking_mint,
+        staking_vault: pool.staking_vault,
+        reward_mint: pool.reward_streams[0].mint,
+        reward_vault: pool.reward_streams[0].vault,
+        reward_duration: pool.reward_duration,
+        reward_duration_end: 0,
+        lock_period: pool.lock_period,
+        last_update_time: 0,
+        reward_rate: 0,
+        reward_per_token_stored: 0,
+        user_stake_count: 1,
+        funders: pool.funders,
+    };
+    let mut data = Pool::discriminator().to_vec();
+    data.extend(legacy.try_to_vec().unwrap());
+    let rent = test.context.banks_client.get_rent().await.unwrap();
+    let mut account = AccountSharedData::new(rent.minimum_balance(data.len()), data.len(), &reward_pool::ID);
+    account.set_data(data);
+    test.context.set_account(&test.pool, &account);
+
+    let instruction = reward_pool_client::stake(
+        &test.pool,
+        &pool,
+        &alice.owner.pubkey(),
+        &alice.stake_account,
+        1_000,
+        pool.lock_period,
+    );
+    assert_error(
+        test.process(&[instruction], &[&alice.owner]).await,
+        anchor_lang::__private::ErrorCode::AccountDidNotDeserialize,
+    );
+    let instruction =
+        reward_pool_client::fund(&test.pool, &pool, 0, &test.payer(), &test.funding_account, FUND_AMOUNT);
+    assert_error(
+        test.process(&[instruction], &[]).await,
+        anchor_lang::__private::ErrorCode::AccountDidNotDeserialize,
+    );
+}
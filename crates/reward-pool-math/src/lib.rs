@@ -0,0 +1,274 @@
+//! Reward accounting of the reward pool program, free of any Solana types so
+//! clients compute exactly what the program does on chain.
+#![no_std]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+use core::convert::TryFrom;
+use core::fmt;
+
+/// Scale of `reward_rate` and `reward_per_token_stored`.
+pub const PRECISION: u128 = u64::MAX as u128;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MathError {
+    Overflow,
+    Underflow,
+    DivisionByZero,
+    InvalidTimestamp,
+    Conversion,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            MathError::Overflow => "arithmetic overflow",
+            MathError::Underflow => "arithmetic underflow",
+            MathError::DivisionByZero => "division by zero",
+            MathError::InvalidTimestamp => "invalid timestamp",
+            MathError::Conversion => "numeric conversion failed",
+        };
+        f.write_str(msg)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MathError {}
+
+pub type Result<T> = core::result::Result<T, MathError>;
+
+/// The accounting state of one reward stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamState {
+    /// Time the current reward period starts emitting.
+    pub reward_start_time: u64,
+    /// Time the current reward period ends.
+    pub reward_duration_end: u64,
+    /// Last time the stream was brought up to date.
+    pub last_update_time: u64,
+    /// Per second reward rate, scaled by `PRECISION`.
+    pub reward_rate: u128,
+    /// Rewards per weighted staked token so far, scaled by `PRECISION`.
+    pub reward_per_token_stored: u128,
+}
+
+/// A stream's rewards held in its vault for stakers, outside the accounting state.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamLiability {
+    /// Rewards accrued to stakers but not yet claimed.
+    pub reward_liability: u64,
+    /// Accrued rewards short of a whole token, scaled by `PRECISION`.
+    pub liability_remainder: u128,
+    /// Rewards emitted while nothing was staked.
+    pub undistributed: u64,
+}
+
+impl StreamState {
+    /// Brings the stream up to `current_time`, returning the rewards emitted since the last update.
+    pub fn accrue(&mut self, total_weighted_stake: u64, current_time: u64) -> Result<u64> {
+        let last_time_reward_applicable = last_time_reward_applicable(
+            self.reward_start_time,
+            self.reward_duration_end,
+            current_time,
+        );
+        let emitted = emitted_rewards(
+            last_time_reward_applicable,
+            self.last_update_time,
+            self.reward_rate,
+        )?;
+        self.reward_per_token_stored = reward_per_token(
+            total_weighted_stake,
+            self.reward_per_token_stored,
+            last_time_reward_applicable,
+            self.last_update_time,
+            self.reward_rate,
+        )?;
+        self.last_update_time = last_time_reward_applicable;
+
+        Ok(emitted)
+    }
+
+    /// Brings the stream up to `current_time` like `accrue`, adding what stakers
+    /// accrued to `liability`, or what was emitted to its undistributed rewards
+    /// when nothing is staked.
+    pub fn accrue_with_liability(
+        &mut self,
+        total_weighted_stake: u64,
+        current_time: u64,
+        liability: &mut StreamLiability,
+    ) -> Result<()> {
+        let reward_per_token_stored = self.reward_per_token_stored;
+        let emitted = self.accrue(total_weighted_stake, current_time)?;
+        if total_weighted_stake == 0 {
+            liability.undistributed = liability.undistributed.checked_add(emitted).ok_or(MathError::Overflow)?;
+        } else {
+            let increase = self.reward_per_token_stored
+                .checked_sub(reward_per_token_stored)
+                .ok_or(MathError::Underflow)?;
+            let (accrued, remainder) = accrued_liability(
+                total_weighted_stake,
+                increase,
+                liability.liability_remainder,
+            )?;
+            liability.reward_liability = liability.reward_liability.checked_add(accrued).ok_or(MathError::Overflow)?;
+            liability.liability_remainder = remainder;
+        }
+
+        Ok(())
+    }
+
+    /// Starts a reward period at `current_time` emitting `amount`, plus whatever
+    /// the running period had left, over `duration`. The stream must be accrued
+    /// up to `current_time` first.
+    pub fn fund(&mut self, amount: u64, duration: u64, current_time: u64) -> Result<()> {
+        let leftover = if current_time >= self.reward_duration_end {
+            0
+        } else {
+            ((self.reward_duration_end - current_time) as u128)
+                .checked_mul(self.reward_rate)
+                .ok_or(MathError::Overflow)?
+        };
+        self.reward_rate = scaled_reward_rate(amount, leftover, duration)?;
+        self.reward_start_time = current_time;
+        self.last_update_time = current_time;
+        self.reward_duration_end = current_time.checked_add(duration).ok_or(MathError::Overflow)?;
+
+        Ok(())
+    }
+}
+
+/// A user's accounting state for one reward stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UserStreamState {
+    /// The user's staked tokens weighted by lock duration.
+    pub balance_weighted: u64,
+    /// `reward_per_token_stored` at the user's last settlement.
+    pub reward_per_token_complete: u128,
+    /// Rewards earned and not yet paid out.
+    pub reward_per_token_pending: u64,
+}
+
+impl UserStreamState {
+    /// Moves rewards earned up to `reward_per_token_stored` into pending.
+    pub fn settle(&mut self, reward_per_token_stored: u128) -> Result<()> {
+        self.reward_per_token_pending = earned(
+            self.balance_weighted,
+            reward_per_token_stored,
+            self.reward_per_token_complete,
+            self.reward_per_token_pending,
+        )?;
+        self.reward_per_token_complete = reward_per_token_stored;
+
+        Ok(())
+    }
+}
+
+/// A user's pending reward as of `current_time`, leaving both states untouched.
+pub fn pending_reward(
+    stream: &StreamState,
+    total_weighted_stake: u64,
+    user: &UserStreamState,
+    current_time: u64,
+) -> Result<u64> {
+    let mut stream = *stream;
+    stream.accrue(total_weighted_stake, current_time)?;
+    let mut user = *user;
+    user.settle(stream.reward_per_token_stored)?;
+
+    Ok(user.reward_per_token_pending)
+}
+
+/// Converts a unix timestamp, which must not be negative.
+pub fn to_timestamp(unix_timestamp: i64) -> Result<u64> {
+    u64::try_from(unix_timestamp).map_err(|_| MathError::InvalidTimestamp)
+}
+
+pub fn last_time_reward_applicable(
+    reward_start_time: u64,
+    reward_duration_end: u64,
+    current_time: u64,
+) -> u64 {
+    core::cmp::min(core::cmp::max(current_time, reward_start_time), reward_duration_end)
+}
+
+pub fn reward_per_token(
+    total_staked: u64,
+    reward_per_token_stored: u128,
+    last_time_reward_applicable: u64,
+    last_update_time: u64,
+    reward_rate: u128,
+) -> Result<u128> {
+    if total_staked == 0 {
+        return Ok(reward_per_token_stored);
+    }
+
+    let elapsed = (last_time_reward_applicable as u128)
+        .checked_sub(last_update_time as u128)
+        .ok_or(MathError::Underflow)?;
+    let increment = elapsed
+        .checked_mul(reward_rate)
+        .ok_or(MathError::Overflow)?
+        / total_staked as u128;
+    reward_per_token_stored
+        .checked_add(increment)
+        .ok_or(MathError::Overflow)
+}
+
+/// Rewards emitted since the last update.
+pub fn emitted_rewards(
+    last_time_reward_applicable: u64,
+    last_update_time: u64,
+    reward_rate: u128,
+) -> Result<u64> {
+    let elapsed = (last_time_reward_applicable as u128)
+        .checked_sub(last_update_time as u128)
+        .ok_or(MathError::Underflow)?;
+    let emitted = elapsed
+        .checked_mul(reward_rate)
+        .ok_or(MathError::Overflow)?
+        / PRECISION;
+    u64::try_from(emitted).map_err(|_| MathError::Conversion)
+}
+
+/// Rewards owed to stakers for a `reward_per_token_stored` increase, in whole
+/// tokens plus the scaled `remainder` carried into the next increase. Stakers
+/// settle from the summed increases, so flooring each increase on its own would
+/// owe less than they can claim.
+pub fn accrued_liability(total_weighted_stake: u64, increase: u128, remainder: u128) -> Result<(u64, u128)> {
+    let scaled = (total_weighted_stake as u128)
+        .checked_mul(increase)
+        .and_then(|v| v.checked_add(remainder))
+        .ok_or(MathError::Overflow)?;
+    let accrued = u64::try_from(scaled / PRECISION).map_err(|_| MathError::Conversion)?;
+    Ok((accrued, scaled % PRECISION))
+}
+
+/// Per second reward rate, scaled by `PRECISION`, emitting `amount` over `duration`.
+pub fn scaled_reward_rate(amount: u64, leftover: u128, duration: u64) -> Result<u128> {
+    (amount as u128)
+        .checked_mul(PRECISION)
+        .and_then(|v| v.checked_add(leftover))
+        .ok_or(MathError::Overflow)?
+        .checked_div(duration as u128)
+        .ok_or(MathError::DivisionByZero)
+}
+
+pub fn earned(
+    balance_staked: u64,
+    reward_per_token: u128,
+    user_reward_per_token_paid: u128,
+    user_reward_pending: u64,
+) -> Result<u64> {
+    let delta = reward_per_token
+        .checked_sub(user_reward_per_token_paid)
+        .ok_or(MathError::Underflow)?;
+    let earned = (balance_staked as u128)
+        .checked_mul(delta)
+        .ok_or(MathError::Overflow)?
+        / PRECISION;
+    let total = earned
+        .checked_add(user_reward_pending as u128)
+        .ok_or(MathError::Overflow)?;
+    u64::try_from(total).map_err(|_| MathError::Conversion)
+}
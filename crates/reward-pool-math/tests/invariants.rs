@@ -0,0 +1,190 @@
+//! Drives a model pool with random stakes, unstakes, fundings, claims and clock
+//! advances, checking the reward accounting invariants after every step.
+
+use proptest::prelude::*;
+use reward_pool_math::{StreamLiability, StreamState, UserStreamState, PRECISION};
+
+const USERS: usize = 3;
+const REWARD_DURATION: u64 = 86_400;
+const START: u64 = 1_700_000_000;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Stake { user: usize, amount: u64 },
+    /// Unstakes `amount` modulo the user's balance plus one.
+    Unstake { user: usize, amount: u64 },
+    Fund { amount: u64 },
+    Claim { user: usize },
+    Advance { seconds: u64 },
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (0..USERS, 1..1_000_000_000_000u64).prop_map(|(user, amount)| Op::Stake { user, amount }),
+        (0..USERS, any::<u64>()).prop_map(|(user, amount)| Op::Unstake { user, amount }),
+        (0..1_000_000_000_000_000u64).prop_map(|amount| Op::Fund { amount }),
+        (0..USERS).prop_map(|user| Op::Claim { user }),
+        (0..2 * REWARD_DURATION).prop_map(|seconds| Op::Advance { seconds }),
+    ]
+}
+
+/// One reward stream accounted the way the program does it.
+#[derive(Clone, Default)]
+struct Model {
+    now: u64,
+    stream: StreamState,
+    liability: StreamLiability,
+    total_weighted_stake: u64,
+    users: [UserStreamState; USERS],
+    /// Exact rewards each user earned, in whole tokens and the rest scaled by
+    /// `PRECISION`, summed from their own stake rather than the pool total.
+    earned: [(u128, u128); USERS],
+    paid: [u64; USERS],
+    total_funded: u64,
+    total_paid: u64,
+}
+
+impl Model {
+    fn new() -> Self {
+        Model {
+            now: START,
+            ..Model::default()
+        }
+    }
+
+    fn update(&mut self, user: Option<usize>) {
+        let reward_per_token_stored = self.stream.reward_per_token_stored;
+        self.stream
+            .accrue_with_liability(self.total_weighted_stake, self.now, &mut self.liability)
+            .unwrap();
+        let increase = self.stream.reward_per_token_stored - reward_per_token_stored;
+        for (user, earned) in self.users.iter().zip(self.earned.iter_mut()) {
+            let scaled = user.balance_weighted as u128 * increase;
+            earned.0 += scaled / PRECISION;
+            earned.1 += scaled % PRECISION;
+            if earned.1 >= PRECISION {
+                earned.0 += 1;
+                earned.1 -= PRECISION;
+            }
+        }
+        if let Some(user) = user {
+            self.users[user].settle(self.stream.reward_per_token_stored).unwrap();
+        }
+    }
+
+    fn apply(&mut self, op: &Op) {
+        match *op {
+            Op::Stake { user, amount } => {
+                self.update(Some(user));
+                self.users[user].balance_weighted += amount;
+                self.total_weighted_stake += amount;
+            }
+            Op::Unstake { user, amount } => {
+                let amount = amount % (self.users[user].balance_weighted + 1);
+                if amount == 0 {
+                    return;
+                }
+                self.update(Some(user));
+                self.users[user].balance_weighted -= amount;
+                self.total_weighted_stake -= amount;
+            }
+            Op::Fund { amount } => {
+                self.update(None);
+                let total_amount = amount + self.liability.undistributed;
+                self.liability.undistributed = 0;
+                self.stream.fund(total_amount, REWARD_DURATION, self.now).unwrap();
+                self.total_funded += amount;
+            }
+            Op::Claim { user } => {
+                self.update(Some(user));
+                let paid = self.users[user].reward_per_token_pending.min(self.vault());
+                self.users[user].reward_per_token_pending -= paid;
+                self.liability.reward_liability =
+                    self.liability.reward_liability.checked_sub(paid).expect("claim exceeds liability");
+                self.paid[user] += paid;
+                self.total_paid += paid;
+            }
+            Op::Advance { seconds } => self.now += seconds,
+        }
+    }
+
+    fn vault(&self) -> u64 {
+        self.total_funded - self.total_paid
+    }
+
+    /// Each user's pending reward as of now, as clients compute it.
+    fn pending(&self) -> [u64; USERS] {
+        let mut pending = [0; USERS];
+        for (i, user) in self.users.iter().enumerate() {
+            pending[i] =
+                reward_pool_math::pending_reward(&self.stream, self.total_weighted_stake, user, self.now).unwrap();
+        }
+        pending
+    }
+}
+
+proptest! {
+    #[test]
+    fn reward_accounting_invariants(ops in prop::collection::vec(op(), 1..64)) {
+        let mut model = Model::new();
+        let mut pending = model.pending();
+
+        for op in &ops {
+            let reward_per_token_stored = model.stream.reward_per_token_stored;
+            model.apply(op);
+            let next_pending = model.pending();
+
+            prop_assert!(model.stream.reward_per_token_stored >= reward_per_token_stored);
+            for user in 0..USERS {
+                if matches!(*op, Op::Claim { user: x } if x == user) {
+                    prop_assert_eq!(next_pending[user], 0);
+                } else {
+                    prop_assert!(next_pending[user] >= pending[user], "user {} pending decreased", user);
+                }
+            }
+
+            let owed: u128 = next_pending.iter().map(|x| *x as u128).sum();
+            prop_assert!(
+                model.total_paid as u128 + owed + model.liability.undistributed as u128 <= model.total_funded as u128,
+                "paid {} + pending {} + undistributed {} exceeds funded {}",
+                model.total_paid,
+                owed,
+                model.liability.undistributed,
+                model.total_funded,
+            );
+
+            // Liability as of now, without moving the model's last update.
+            let mut settled = model.clone();
+            settled.update(None);
+            let liability = settled.liability;
+            prop_assert!(
+                settled.total_paid as u128 + liability.reward_liability as u128 <= settled.total_funded as u128,
+                "paid {} + liability {} exceeds funded {}",
+                settled.total_paid,
+                liability.reward_liability,
+                settled.total_funded,
+            );
+            prop_assert!(
+                liability.reward_liability as u128 + liability.undistributed as u128 <= settled.vault() as u128,
+                "liability {} + undistributed {} exceeds vault {}",
+                liability.reward_liability,
+                liability.undistributed,
+                settled.vault(),
+            );
+
+            // The liability is what users earned, to the scaled rest, less what was paid.
+            let earned_whole: u128 = settled.earned.iter().map(|x| x.0).sum();
+            let earned_rest: u128 = settled.earned.iter().map(|x| x.1).sum();
+            prop_assert_eq!(
+                liability.reward_liability as u128 + settled.total_paid as u128,
+                earned_whole + earned_rest / PRECISION,
+            );
+            prop_assert_eq!(liability.liability_remainder, earned_rest % PRECISION);
+            for (user, pending) in next_pending.iter().enumerate() {
+                let unpaid = settled.earned[user].0 - settled.paid[user] as u128;
+                prop_assert!(*pending as u128 <= unpaid, "user {} pending above earned", user);
+            }
+            pending = next_pending;
+        }
+    }
+}
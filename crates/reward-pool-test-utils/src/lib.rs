@@ -0,0 +1,536 @@
+//! Runs the reward pool program in a local bank for the integration tests and
+//! fuzz targets, with helpers creating pools, users and token accounts.
+
+use anchor_lang::__private::base64;
+use anchor_lang::{AccountSerialize, AnchorDeserialize, Discriminator};
+use reward_pool_client::reward_pool::{self, PenaltyCurve, PenaltyDestination, RewardLockPolicy, BPS_DENOMINATOR, MIN_DURATION};
+use reward_pool_client::{InitializePoolAccounts, Pool, PoolConfig, User};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::account::AccountSharedData;
+use solana_sdk::account_info::AccountInfo;
+use solana_sdk::clock::Clock;
+use solana_sdk::entrypoint::ProgramResult;
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::program_error::ProgramError;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::program_stubs::{self, SyscallStubs};
+use solana_sdk::program_utils::limited_deserialize;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction::{self, SystemInstruction};
+use solana_sdk::system_program;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use solana_sdk::transport::TransportError;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, Once};
+
+pub const START: i64 = 1_700_000_000;
+pub const REWARD_DURATION: u64 = MIN_DURATION;
+pub const LOCK_PERIOD: u64 = 3_600;
+/// Emits 1000 tokens a second over the reward duration.
+pub const FUND_AMOUNT: u64 = 1_000 * REWARD_DURATION;
+
+/// A pool in a fresh local bank, with mints and vaults owned by the payer.
+pub struct TestPool {
+    pub context: ProgramTestContext,
+    pub pool: Pubkey,
+    pub staking_mint: Pubkey,
+    pub reward_mint: Pubkey,
+    pub staking_vault: Pubkey,
+    pub reward_vault: Pubkey,
+    /// Reward tokens of the authority, used to fund the pool.
+    pub funding_account: Pubkey,
+}
+
+/// A user of the pool with token accounts for the staking and reward mints.
+pub struct Staker {
+    pub owner: Keypair,
+    pub stake_account: Pubkey,
+    pub reward_account: Pubkey,
+}
+
+/// A config without lock multipliers, early unstaking, reward locks or vesting.
+pub fn config(lock_period: u64) -> PoolConfig {
+    PoolConfig {
+        max_lock_period: lock_period,
+        max_lock_multiplier_bps: BPS_DENOMINATOR,
+        penalty_curve: PenaltyCurve::Disabled,
+        early_unstake_penalty_bps: 0,
+        penalty_destination: PenaltyDestination::Treasury,
+        reward_lock_policy: RewardLockPolicy::WithPrincipal,
+        reward_lock_period: 0,
+        compound_resets_lock: true,
+        vesting_period: 0,
+        vesting_cliff: 0,
+    }
+}
+
+/// Return data set by programs in every bank, oldest first.
+static RETURN_DATA: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+/// Messages logged by programs in every bank once recording started, oldest first.
+static LOGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Off by default, so long fuzzing runs don't keep every log.
+static RECORD_LOGS: AtomicBool = AtomicBool::new(false);
+
+/// Panic raised by the bank's syscall stubs when a CPI resizes an account.
+const RESIZE_PANIC: &str = "Account data resizing not supported yet";
+
+/// The bank's syscall stubs apply a CPI to the accounts it touches, but then panic
+/// when copying resized data back to a natively run caller. These stubs finish
+/// such a system program CPI by resizing the caller's account in place, so `init`
+/// and associated token account creation run as they would on chain.
+pub struct ResizingSyscallStubs(Box<dyn SyscallStubs>);
+
+struct NoSyscallStubs;
+
+impl SyscallStubs for NoSyscallStubs {}
+
+impl ResizingSyscallStubs {
+    /// Swaps these stubs in front of the bank's, which are set when the first bank starts.
+    pub fn install() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            let default_hook = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                if !is_resize_panic(info.payload()) {
+                    default_hook(info);
+                }
+            }));
+            let bank_stubs = program_stubs::set_syscall_stubs(Box::new(NoSyscallStubs));
+            program_stubs::set_syscall_stubs(Box::new(ResizingSyscallStubs(bank_stubs)));
+        });
+    }
+}
+
+fn is_resize_panic(payload: &(dyn Any + Send)) -> bool {
+    match payload.downcast_ref::<String>() {
+        Some(message) => message.starts_with(RESIZE_PANIC),
+        None => false,
+    }
+}
+
+/// The account a system instruction sizes and its new size.
+fn resized_account(instruction: &Instruction) -> Option<(Pubkey, usize)> {
+    if instruction.program_id != system_program::ID {
+        return None;
+    }
+    let (index, space) = match limited_deserialize(&instruction.data).ok()? {
+        SystemInstruction::CreateAccount { space, .. } | SystemInstruction::CreateAccountWithSeed { space, .. } => {
+            (1, space)
+        }
+        SystemInstruction::Allocate { space } | SystemInstruction::AllocateWithSeed { space, .. } => (0, space),
+        _ => return None,
+    };
+    Some((instruction.accounts[index].pubkey, space as usize))
+}
+
+impl SyscallStubs for ResizingSyscallStubs {
+    fn sol_log(&self, message: &str) {
+        if RECORD_LOGS.load(Ordering::Relaxed) {
+            LOGS.lock().unwrap().push(message.to_owned());
+        }
+        self.0.sol_log(message)
+    }
+
+    fn sol_log_compute_units(&self) {
+        self.0.sol_log_compute_units()
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let (address, space) = match resized_account(instruction) {
+            Some(resized) => resized,
+            None => return self.0.sol_invoke_signed(instruction, account_infos, signers_seeds),
+        };
+
+        // Lamports and owners are copied back before the resized data, and the
+        // funding account precedes the new one.
+        match panic::catch_unwind(AssertUnwindSafe(|| {
+            self.0.sol_invoke_signed(instruction, account_infos, signers_seeds)
+        })) {
+            Ok(result) => result,
+            Err(payload) if is_resize_panic(&*payload) => {
+                let account_info = account_infos.iter().find(|x| *x.key == address).unwrap();
+                // The system program zero-fills allocated data.
+                *account_info.try_borrow_mut_data()? = Box::leak(vec![0; space].into_boxed_slice());
+                Ok(())
+            }
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_clock_sysvar(var_addr)
+    }
+
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_epoch_schedule_sysvar(var_addr)
+    }
+
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_fees_sysvar(var_addr)
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_rent_sysvar(var_addr)
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        self.0.sol_get_return_data()
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        RETURN_DATA.lock().unwrap().push(data.to_vec());
+        self.0.sol_set_return_data(data)
+    }
+
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        self.0.sol_log_data(fields)
+    }
+}
+
+/// Return data set so far by programs of any test, as transaction simulation
+/// isn't available to read it from.
+pub fn return_data() -> Vec<Vec<u8>> {
+    RETURN_DATA.lock().unwrap().clone()
+}
+
+/// Starts keeping the messages programs log, for `events` to decode.
+pub fn record_events() {
+    RECORD_LOGS.store(true, Ordering::Relaxed);
+}
+
+/// Events of type `T` emitted by programs of any test since recording started.
+pub fn events<T: AnchorDeserialize + Discriminator>() -> Vec<T> {
+    LOGS.lock()
+        .unwrap()
+        .iter()
+        .filter_map(|message| {
+            let data = base64::decode(message).ok()?;
+            if data.len() < 8 || data[..8] != T::discriminator() {
+                return None;
+            }
+            T::try_from_slice(&data[8..]).ok()
+        })
+        .collect()
+}
+
+/// The custom error code of a program error.
+pub fn error_code(err: impl Into<ProgramError>) -> u32 {
+    match err.into() {
+        ProgramError::Custom(code) => code,
+        err => panic!("not a custom error: {:?}", err),
+    }
+}
+
+impl TestPool {
+    /// Starts a bank at `START` with fresh mints and a pool funded by nobody yet.
+    pub async fn new() -> Self {
+        Self::with_lock_period(LOCK_PERIOD).await
+    }
+
+    pub async fn with_lock_period(lock_period: u64) -> Self {
+        Self::start(lock_period, config(lock_period), false).await
+    }
+
+    /// Starts a pool paying rewards in the staking mint, which can be compounded.
+    pub async fn compounding(lock_period: u64) -> Self {
+        Self::start(lock_period, config(lock_period), true).await
+    }
+
+    pub async fn start(lock_period: u64, config: PoolConfig, compounding: bool) -> Self {
+        let mut program = ProgramTest::new("reward_pool", reward_pool::ID, processor!(reward_pool::process_instruction));
+        // The bundled spl-token build crashes the interpreter, run it natively too.
+        program.add_program("spl_token", spl_token::ID, processor!(spl_token::processor::Processor::process));
+        program.add_program(
+            "spl_associated_token_account",
+            spl_associated_token_account::ID,
+            processor!(spl_associated_token_account::processor::process_instruction),
+        );
+        let mut context = program.start_with_context().await;
+        ResizingSyscallStubs::install();
+        warp_to(&mut context, START).await;
+
+        let mut test = TestPool {
+            context,
+            pool: Pubkey::default(),
+            staking_mint: Pubkey::default(),
+            reward_mint: Pubkey::default(),
+            staking_vault: Pubkey::default(),
+            reward_vault: Pubkey::default(),
+            funding_account: Pubkey::default(),
+        };
+        test.staking_mint = test.create_mint(&test.payer()).await;
+        test.reward_mint = if compounding {
+            test.staking_mint
+        } else {
+            test.create_mint(&test.payer()).await
+        };
+        test.funding_account = test.create_token_account(&test.reward_mint.clone(), &test.payer()).await;
+        test.mint_to(&test.reward_mint.clone(), &test.funding_account.clone(), 10 * FUND_AMOUNT).await;
+
+        let pool = Keypair::new();
+        let pool_signer = reward_pool_client::pool_signer_address(&pool.pubkey()).0;
+        test.pool = pool.pubkey();
+        test.staking_vault = test.create_token_account(&test.staking_mint.clone(), &pool_signer).await;
+        test.reward_vault = test.create_token_account(&test.reward_mint.clone(), &pool_signer).await;
+        let treasury = test.create_token_account(&test.staking_mint.clone(), &test.payer()).await;
+
+        let rent = test.context.banks_client.get_rent().await.unwrap();
+        let instructions = [
+            reward_pool_client::create_pool_account(
+                &test.payer(),
+                &pool.pubkey(),
+                rent.minimum_balance(reward_pool_client::pool_account_size()),
+            ),
+            reward_pool_client::initialize_pool(
+                &InitializePoolAccounts {
+                    pool: pool.pubkey(),
+                    authority: test.payer(),
+                    staking_mint: test.staking_mint,
+                    staking_vault: test.staking_vault,
+                    reward_mint: test.reward_mint,
+                    reward_vault: test.reward_vault,
+                    treasury,
+                },
+                REWARD_DURATION,
+                lock_period,
+                config,
+            ),
+        ];
+        test.process(&instructions, &[&pool]).await.unwrap();
+        test
+    }
+
+    pub fn payer(&self) -> Pubkey {
+        self.context.payer.pubkey()
+    }
+
+    pub async fn process(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), TransportError> {
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+        self.context.banks_client.process_transaction(transaction).await
+    }
+
+    pub async fn create_mint(&mut self, authority: &Pubkey) -> Pubkey {
+        let mint = Keypair::new();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let instructions = [
+            system_instruction::create_account(
+                &self.payer(),
+                &mint.pubkey(),
+                rent.minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint(&spl_token::ID, &mint.pubkey(), authority, None, 0).unwrap(),
+        ];
+        self.process(&instructions, &[&mint]).await.unwrap();
+        mint.pubkey()
+    }
+
+    pub async fn create_token_account(&mut self, mint: &Pubkey, owner: &Pubkey) -> Pubkey {
+        let account = Keypair::new();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let instructions = [
+            system_instruction::create_account(
+                &self.payer(),
+                &account.pubkey(),
+                rent.minimum_balance(spl_token::state::Account::LEN),
+                spl_token::state::Account::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_account(&spl_token::ID, &account.pubkey(), mint, owner).unwrap(),
+        ];
+        self.process(&instructions, &[&account]).await.unwrap();
+        account.pubkey()
+    }
+
+    pub async fn mint_to(&mut self, mint: &Pubkey, account: &Pubkey, amount: u64) {
+        let instruction =
+            spl_token::instruction::mint_to(&spl_token::ID, mint, account, &self.payer(), &[], amount).unwrap();
+        self.process(&[instruction], &[]).await.unwrap();
+    }
+
+    pub async fn token_balance(&mut self, account: &Pubkey) -> u64 {
+        let account = self.context.banks_client.get_account(*account).await.unwrap().unwrap();
+        spl_token::state::Account::unpack(&account.data).unwrap().amount
+    }
+
+    pub async fn pool_state(&mut self) -> Pool {
+        let account = self.context.banks_client.get_account(self.pool).await.unwrap().unwrap();
+        reward_pool_client::deserialize_pool(&account.data).unwrap()
+    }
+
+    /// Overwrites the pool account, standing in for pools written by earlier versions.
+    pub async fn set_pool_state(&mut self, pool: &Pool) {
+        let account = self.context.banks_client.get_account(self.pool).await.unwrap().unwrap();
+        let mut data = Vec::with_capacity(account.data.len());
+        pool.try_serialize(&mut data).unwrap();
+        data.resize(account.data.len(), 0);
+        let mut account = AccountSharedData::from(account);
+        account.set_data(data);
+        self.context.set_account(&self.pool, &account);
+    }
+
+    pub async fn user_state(&mut self, staker: &Staker) -> User {
+        let user = reward_pool_client::user_address(&staker.owner.pubkey(), &self.pool).0;
+        let account = self.context.banks_client.get_account(user).await.unwrap().unwrap();
+        reward_pool_client::deserialize_user(&account.data).unwrap()
+    }
+
+    /// Pending rewards of the first stream as of the current clock.
+    pub async fn pending(&mut self, staker: &Staker) -> u64 {
+        let pool = self.pool_state().await;
+        let user = self.user_state(staker).await;
+        let clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        reward_pool_client::pending_rewards(&pool, &user, clock.unix_timestamp as u64).unwrap()[0]
+    }
+
+    /// A new user of the pool holding `amount` staking tokens.
+    pub async fn staker(&mut self, amount: u64) -> Staker {
+        let owner = Keypair::new();
+        let stake_account = self.create_token_account(&self.staking_mint.clone(), &owner.pubkey()).await;
+        let reward_account = self.create_token_account(&self.reward_mint.clone(), &owner.pubkey()).await;
+        self.mint_to(&self.staking_mint.clone(), &stake_account, amount).await;
+        self.create_user(&owner).await;
+        Staker { owner, stake_account, reward_account }
+    }
+
+    /// Funds the owner, who pays for their user account, and creates it.
+    pub async fn create_user(&mut self, owner: &Keypair) {
+        let instructions = [
+            system_instruction::transfer(&self.payer(), &owner.pubkey(), 1_000_000_000),
+            reward_pool_client::create_user(&self.pool, &owner.pubkey()),
+        ];
+        self.process(&instructions, &[owner]).await.unwrap();
+    }
+
+    /// Enables receipts for a fresh mint owned by the pool signer, returning the mint.
+    pub async fn enable_receipts(&mut self) -> Pubkey {
+        let pool_signer = reward_pool_client::pool_signer_address(&self.pool).0;
+        let receipt_mint = self.create_mint(&pool_signer).await;
+        let pool = self.pool_state().await;
+        let instruction = reward_pool_client::enable_receipts(&self.pool, &pool, &receipt_mint);
+        self.process(&[instruction], &[]).await.unwrap();
+        receipt_mint
+    }
+
+    /// Creates the owner's receipt account, their associated token account.
+    pub async fn create_receipt_account(&mut self, staker: &Staker) -> Pubkey {
+        let pool = self.pool_state().await;
+        let instruction = spl_associated_token_account::create_associated_token_account(
+            &self.payer(),
+            &staker.owner.pubkey(),
+            &pool.receipt_mint,
+        );
+        self.process(&[instruction], &[]).await.unwrap();
+        pool.receipt_account(&staker.owner.pubkey())
+    }
+
+    pub async fn sync_receipt(&mut self, staker: &Staker) -> Result<(), TransportError> {
+        let pool = self.pool_state().await;
+        let instruction = reward_pool_client::sync_receipt(&self.pool, &pool, &staker.owner.pubkey());
+        self.process(&[instruction], &[]).await
+    }
+
+    pub async fn fund(&mut self, amount: u64) -> Result<(), TransportError> {
+        let pool = self.pool_state().await;
+        let instruction =
+            reward_pool_client::fund(&self.pool, &pool, 0, &self.payer(), &self.funding_account, amount);
+        self.process(&[instruction], &[]).await
+    }
+
+    /// Stakes for the pool's minimum lock period.
+    pub async fn stake(&mut self, staker: &Staker, amount: u64) -> Result<(), TransportError> {
+        let lock_period = self.pool_state().await.lock_period;
+        self.stake_locked(staker, amount, lock_period).await
+    }
+
+    pub async fn stake_locked(&mut self, staker: &Staker, amount: u64, lock_duration: u64) -> Result<(), TransportError> {
+        let pool = self.pool_state().await;
+        let instruction = reward_pool_client::stake(
+            &self.pool,
+            &pool,
+            &staker.owner.pubkey(),
+            &staker.stake_account,
+            amount,
+            lock_duration,
+        );
+        self.process(&[instruction], &[&staker.owner]).await
+    }
+
+    pub async fn unstake(&mut self, staker: &Staker, amount: u64) -> Result<(), TransportError> {
+        let pool = self.pool_state().await;
+        let instruction =
+            reward_pool_client::unstake(&self.pool, &pool, &staker.owner.pubkey(), &staker.stake_account, amount);
+        self.process(&[instruction], &[&staker.owner]).await
+    }
+
+    pub async fn claim(&mut self, staker: &Staker) -> Result<(), TransportError> {
+        let pool = self.pool_state().await;
+        let instruction =
+            reward_pool_client::claim(&self.pool, &pool, 0, &staker.owner.pubkey(), &staker.reward_account);
+        self.process(&[instruction], &[&staker.owner]).await
+    }
+
+    pub async fn early_unstake(&mut self, staker: &Staker, amount: u64) -> Result<(), TransportError> {
+        let pool = self.pool_state().await;
+        let instruction =
+            reward_pool_client::early_unstake(&self.pool, &pool, &staker.owner.pubkey(), &staker.stake_account, amount);
+        self.process(&[instruction], &[&staker.owner]).await
+    }
+
+    pub async fn compound(&mut self, staker: &Staker) -> Result<(), TransportError> {
+        let pool = self.pool_state().await;
+        let instruction = reward_pool_client::compound(&self.pool, &pool, 0, &staker.owner.pubkey());
+        self.process(&[instruction], &[&staker.owner]).await
+    }
+
+    pub async fn pause(&mut self, pauser: &Keypair, flags: u8) -> Result<(), TransportError> {
+        let instruction = reward_pool_client::pause(&self.pool, &pauser.pubkey(), flags);
+        self.process(&[instruction], &[pauser]).await
+    }
+
+    pub async fn unpause(&mut self, flags: u8) -> Result<(), TransportError> {
+        let instruction = reward_pool_client::unpause(&self.pool, &self.payer(), flags);
+        self.process(&[instruction], &[]).await
+    }
+
+    pub async fn warp_to(&mut self, unix_timestamp: i64) {
+        warp_to(&mut self.context, unix_timestamp).await;
+    }
+}
+
+/// Moves to a later slot, for a fresh blockhash, and sets the clock to `unix_timestamp`.
+pub async fn warp_to(context: &mut ProgramTestContext, unix_timestamp: i64) {
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    // Warping goes through the slot before the target, which must be new too.
+    context.warp_to_slot(clock.slot + 2).unwrap();
+    let clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    context.set_sysvar(&Clock { unix_timestamp, ..clock });
+}
+
+#[track_caller]
+pub fn assert_error(result: Result<(), TransportError>, err: impl Into<ProgramError>) {
+    let err = error_code(err);
+    match result {
+        Err(TransportError::TransactionError(TransactionError::InstructionError(_, InstructionError::Custom(code)))) => {
+            assert_eq!(code, err)
+        }
+        result => panic!("expected custom error {}, got {:?}", err, result),
+    }
+}
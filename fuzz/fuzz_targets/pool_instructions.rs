@@ -0,0 +1,339 @@
+//! Runs arbitrary sequences of pool instructions in a local bank. Panics in the
+//! program abort the fuzzer, instructions may only fail for the reasons their
+//! inputs allow, and after every instruction the vaults must still hold what the
+//! pool owes its stakers.
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use reward_pool_client::reward_pool::{ErrorCode, PenaltyCurve, PenaltyDestination, PAUSE_CLAIMS};
+use reward_pool_client::PoolConfig;
+use reward_pool_test_utils::{config, error_code, Staker, TestPool, LOCK_PERIOD, REWARD_DURATION, START};
+use solana_sdk::instruction::{Instruction, InstructionError};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::TransactionError;
+use solana_sdk::transport::TransportError;
+use spl_token::error::TokenError;
+
+const USERS: usize = 3;
+const STREAMS: usize = 2;
+const MAX_ACTIONS: usize = 32;
+/// Balances a few of the largest amounts exhaust.
+const STAKE_BALANCE: u64 = 1 << 33;
+const FUNDING_BALANCE: u64 = 1 << 34;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    /// Claims vest instead of paying out.
+    vesting: bool,
+    /// Early unstake penalties go to the other stakers rather than the treasury.
+    penalty_to_stakers: bool,
+    actions: Vec<Action>,
+}
+
+#[derive(Arbitrary, Debug)]
+enum Action {
+    Stake { user: u8, amount: u32, extra_lock: u16 },
+    Unstake { user: u8, amount: u32 },
+    EarlyUnstake { user: u8, amount: u32 },
+    Fund { stream: u8, amount: u32 },
+    /// Claims, or moves rewards into a vesting entry in vesting pools.
+    Claim { user: u8, stream: u8 },
+    WithdrawVested { user: u8, stream: u8 },
+    Compound { user: u8 },
+    Pause { flags: u8 },
+    Unpause { flags: u8 },
+    Advance { seconds: u32 },
+}
+
+struct RewardStream {
+    vault: Pubkey,
+    funding_account: Pubkey,
+}
+
+struct Harness {
+    test: TestPool,
+    now: i64,
+    vesting: bool,
+    /// The first stream pays in the staking mint so rewards can be compounded.
+    streams: Vec<RewardStream>,
+    stakers: Vec<Staker>,
+    /// Each staker's token account per stream.
+    reward_accounts: Vec<[Pubkey; STREAMS]>,
+}
+
+impl Harness {
+    async fn new(vesting: bool, penalty_to_stakers: bool) -> Self {
+        let config = PoolConfig {
+            max_lock_period: 4 * LOCK_PERIOD,
+            max_lock_multiplier_bps: 20_000,
+            penalty_curve: PenaltyCurve::LinearDecay,
+            early_unstake_penalty_bps: 500,
+            penalty_destination: if penalty_to_stakers {
+                PenaltyDestination::Stakers
+            } else {
+                PenaltyDestination::Treasury
+            },
+            vesting_period: if vesting { REWARD_DURATION } else { 0 },
+            vesting_cliff: if vesting { LOCK_PERIOD } else { 0 },
+            ..config(LOCK_PERIOD)
+        };
+        let mut test = TestPool::start(LOCK_PERIOD, config, true).await;
+        let payer = test.payer();
+        test.mint_to(&test.staking_mint.clone(), &test.funding_account.clone(), FUNDING_BALANCE).await;
+        let mut streams = vec![RewardStream {
+            vault: test.reward_vault,
+            funding_account: test.funding_account,
+        }];
+
+        let pool_signer = reward_pool_client::pool_signer_address(&test.pool).0;
+        let mint = test.create_mint(&payer).await;
+        let vault = test.create_token_account(&mint, &pool_signer).await;
+        let funding_account = test.create_token_account(&mint, &payer).await;
+        test.mint_to(&mint, &funding_account, FUNDING_BALANCE).await;
+        let instruction = reward_pool_client::add_reward_stream(&test.pool, &payer, &mint, &vault);
+        test.process(&[instruction], &[]).await.unwrap();
+        streams.push(RewardStream { vault, funding_account });
+
+        let mut stakers = Vec::new();
+        let mut reward_accounts = Vec::new();
+        for _ in 0..USERS {
+            let staker = test.staker(STAKE_BALANCE).await;
+            let reward_account = test.create_token_account(&mint, &staker.owner.pubkey()).await;
+            if vesting {
+                let instruction = reward_pool_client::create_vesting(&test.pool, &staker.owner.pubkey());
+                test.process(&[instruction], &[&staker.owner]).await.unwrap();
+            }
+            reward_accounts.push([staker.reward_account, reward_account]);
+            stakers.push(staker);
+        }
+
+        Harness {
+            test,
+            now: START,
+            vesting,
+            streams,
+            stakers,
+            reward_accounts,
+        }
+    }
+
+    async fn apply(&mut self, action: &Action) {
+        if let Action::Advance { seconds } = *action {
+            self.now += seconds as i64;
+        }
+        // A new slot for every action, as transactions repeated within one are dropped.
+        self.test.warp_to(self.now).await;
+
+        let pool_key = self.test.pool;
+        let pool = self.test.pool_state().await;
+        let payer = self.test.payer();
+        let (instruction, user, expected) = match *action {
+            Action::Stake { user, amount, extra_lock } => {
+                let amount = u64::from(amount);
+                let staker = &self.stakers[user as usize % USERS];
+                let instruction = reward_pool_client::stake(
+                    &pool_key,
+                    &pool,
+                    &staker.owner.pubkey(),
+                    &staker.stake_account,
+                    amount,
+                    LOCK_PERIOD + extra_lock as u64,
+                );
+                let mut expected = vec![
+                    error_code(ErrorCode::AmountMustBeGreaterThanZero),
+                    error_code(ErrorCode::InvalidLockDuration),
+                    error_code(ErrorCode::MaxStakePositions),
+                    error_code(ErrorCode::PoolPaused),
+                ];
+                let stake_account = staker.stake_account;
+                if amount > self.test.token_balance(&stake_account).await {
+                    expected.push(error_code(TokenError::InsufficientFunds));
+                }
+                (instruction, Some(user), expected)
+            }
+            Action::Unstake { user, amount } => {
+                let amount = u64::from(amount);
+                let staker = &self.stakers[user as usize % USERS];
+                let instruction =
+                    reward_pool_client::unstake(&pool_key, &pool, &staker.owner.pubkey(), &staker.stake_account, amount);
+                let expected = vec![
+                    error_code(ErrorCode::AmountMustBeGreaterThanZero),
+                    error_code(ErrorCode::PoolPaused),
+                    error_code(ErrorCode::InsufficientFundUnstake),
+                    error_code(ErrorCode::CannotStakeOrClaimBeforeMaturity),
+                ];
+                (instruction, Some(user), expected)
+            }
+            Action::EarlyUnstake { user, amount } => {
+                let amount = u64::from(amount);
+                let staker = &self.stakers[user as usize % USERS];
+                let instruction = reward_pool_client::early_unstake(
+                    &pool_key,
+                    &pool,
+                    &staker.owner.pubkey(),
+                    &staker.stake_account,
+                    amount,
+                );
+                let expected = vec![
+                    error_code(ErrorCode::AmountMustBeGreaterThanZero),
+                    error_code(ErrorCode::PoolPaused),
+                    error_code(ErrorCode::InsufficientFundUnstake),
+                ];
+                (instruction, Some(user), expected)
+            }
+            Action::Fund { stream, amount } => {
+                let amount = u64::from(amount);
+                let stream = stream as usize % STREAMS;
+                let funding_account = self.streams[stream].funding_account;
+                let instruction =
+                    reward_pool_client::fund(&pool_key, &pool, stream as u8, &payer, &funding_account, amount);
+                let mut expected = vec![error_code(anchor_lang::__private::ErrorCode::ConstraintRaw)];
+                if amount > self.test.token_balance(&funding_account).await {
+                    expected.push(error_code(TokenError::InsufficientFunds));
+                }
+                (instruction, None, expected)
+            }
+            Action::Claim { user, stream } => {
+                self.claim(user as usize % USERS, stream as usize % STREAMS).await;
+                return;
+            }
+            // Only vesting pools create vesting accounts.
+            Action::WithdrawVested { .. } if !self.vesting => return,
+            Action::WithdrawVested { user, stream } => {
+                let user = user as usize % USERS;
+                let stream = stream as usize % STREAMS;
+                let instruction = reward_pool_client::withdraw_vested(
+                    &pool_key,
+                    &pool,
+                    stream as u8,
+                    &self.stakers[user].owner.pubkey(),
+                    &self.reward_accounts[user][stream],
+                );
+                let expected = vec![error_code(ErrorCode::PoolPaused), error_code(ErrorCode::NothingVested)];
+                (instruction, Some(user as u8), expected)
+            }
+            Action::Compound { user } => {
+                let staker = &self.stakers[user as usize % USERS];
+                let instruction = reward_pool_client::compound(&pool_key, &pool, 0, &staker.owner.pubkey());
+                let expected = vec![
+                    error_code(ErrorCode::PoolPaused),
+                    error_code(ErrorCode::AmountMustBeGreaterThanZero),
+                    error_code(ErrorCode::MaxStakePositions),
+                ];
+                (instruction, Some(user), expected)
+            }
+            Action::Pause { flags } => {
+                let instruction = reward_pool_client::pause(&pool_key, &payer, flags);
+                (instruction, None, vec![error_code(ErrorCode::InvalidPauseFlags)])
+            }
+            Action::Unpause { flags } => {
+                let instruction = reward_pool_client::unpause(&pool_key, &payer, flags);
+                (instruction, None, vec![error_code(ErrorCode::InvalidPauseFlags)])
+            }
+            Action::Advance { .. } => return,
+        };
+
+        let result = self.process(instruction, user).await;
+        check_result(action, result, &expected);
+    }
+
+    /// Claims must go through once rewards are unlocked and claims aren't paused,
+    /// paying everything pending as far as the reward vault covers it.
+    async fn claim(&mut self, user: usize, stream: usize) {
+        let pool = self.test.pool_state().await;
+        let user_state = self.test.user_state(&self.stakers[user]).await;
+        let pending = reward_pool_client::pending_rewards(&pool, &user_state, self.now as u64).unwrap()[stream];
+        let reward_account = self.reward_accounts[user][stream];
+        let owner = self.stakers[user].owner.pubkey();
+        let (instruction, mut expected) = if self.vesting {
+            let instruction = reward_pool_client::claim_vesting(&self.test.pool, stream as u8, &owner);
+            (instruction, vec![error_code(ErrorCode::MaxVestingEntries)])
+        } else {
+            let instruction = reward_pool_client::claim(&self.test.pool, &pool, stream as u8, &owner, &reward_account);
+            (instruction, Vec::new())
+        };
+        if self.vesting && pending == 0 {
+            expected.push(error_code(ErrorCode::AmountMustBeGreaterThanZero));
+        }
+        if pool.is_paused(PAUSE_CLAIMS) {
+            expected.push(error_code(ErrorCode::PoolPaused));
+        }
+        if pool.claim_unlock_time(&user_state) > self.now as u64 {
+            expected.push(error_code(ErrorCode::CannotStakeOrClaimBeforeMaturity));
+        }
+
+        let balance = self.test.token_balance(&reward_account).await;
+        let reward_vault = self.test.token_balance(&self.streams[stream].vault).await;
+        let outstanding = pool.reward_streams[stream].vesting_outstanding;
+        let result = self.process(instruction, Some(user as u8)).await;
+        let claimed = result.is_ok();
+        check_result(&Action::Claim { user: user as u8, stream: stream as u8 }, result, &expected);
+        if !claimed {
+            return;
+        }
+
+        if self.vesting {
+            let outstanding_after = self.test.pool_state().await.reward_streams[stream].vesting_outstanding;
+            assert_eq!(outstanding_after - outstanding, pending, "vested less than pending");
+        } else {
+            let paid = self.test.token_balance(&reward_account).await - balance;
+            assert_eq!(paid, pending.min(reward_vault), "paid less than pending");
+        }
+    }
+
+    async fn process(&mut self, instruction: Instruction, user: Option<u8>) -> Result<(), TransportError> {
+        let stakers = &self.stakers;
+        let signers: Vec<&Keypair> = user.map(|user| &stakers[user as usize % USERS].owner).into_iter().collect();
+        self.test.process(&[instruction], &signers).await
+    }
+
+    /// The staking vault holds every user's stake and each reward vault what
+    /// stakers have accrued, vested or will be paid when unstaked emissions roll over.
+    async fn check_invariants(&mut self) {
+        let pool = self.test.pool_state().await;
+
+        let mut staked: u64 = 0;
+        for i in 0..USERS {
+            staked += self.test.user_state(&self.stakers[i]).await.balance_staked;
+        }
+        assert_eq!(staked, pool.total_staked);
+        let staking_vault = self.test.token_balance(&self.test.staking_vault.clone()).await;
+        assert!(staking_vault >= staked, "staking vault {} holds less than staked {}", staking_vault, staked);
+
+        for (i, stream) in pool.reward_streams.iter().take(STREAMS).enumerate() {
+            let owed =
+                stream.reward_liability as u128 + stream.vesting_outstanding as u128 + stream.undistributed as u128;
+            let reward_vault = self.test.token_balance(&self.streams[i].vault).await;
+            assert!(
+                reward_vault as u128 >= owed,
+                "reward vault {} of stream {} holds less than owed {}",
+                reward_vault,
+                i,
+                owed,
+            );
+        }
+    }
+}
+
+/// Panics unless the instruction succeeded or failed with one of the `expected` codes.
+fn check_result(action: &Action, result: Result<(), TransportError>, expected: &[u32]) {
+    match result {
+        Ok(()) => {}
+        Err(TransportError::TransactionError(TransactionError::InstructionError(_, InstructionError::Custom(code))))
+            if expected.contains(&code) => {}
+        Err(err) => panic!("{:?} failed unexpectedly: {:?}", action, err),
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let mut harness = Harness::new(input.vesting, input.penalty_to_stakers).await;
+        for action in input.actions.iter().take(MAX_ACTIONS) {
+            harness.apply(action).await;
+            harness.check_invariants().await;
+        }
+    });
+});
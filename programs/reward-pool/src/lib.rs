@@ -1,96 +1,205 @@
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::{sysvar, clock, program_option::COption};
+use anchor_lang::solana_program::{clock, program_option::COption};
+use anchor_spl::associated_token::get_associated_token_address;
 use anchor_spl::token::{self, TokenAccount, Token, Mint};
 use std::convert::Into;
-use std::convert::TryInto;
 use std::convert::TryFrom;
+use reward_pool_math::{MathError, StreamLiability, StreamState, UserStreamState};
 
-declare_id!("7XDST8WpuVzZpYqaoVsw5pYJ4NEqvFxkyegx4nK34FMP");
+declare_id!("EE753XQHN7kDh3PY8Li3GXwExo4D8Wm9xpVoQ2AqGpBG");
 
-pub const PRECISION: u128 = u64::MAX as u128;
+pub use reward_pool_math::PRECISION;
 pub const MIN_DURATION: u64 = 86400;
+pub const MAX_REWARD_STREAMS: usize = 4;
+pub const MAX_STAKE_POSITIONS: usize = 16;
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const MAX_VESTING_ENTRIES: usize = 8;
+
+pub const PAUSE_DEPOSITS: u8 = 1 << 0;
+pub const PAUSE_WITHDRAWALS: u8 = 1 << 1;
+pub const PAUSE_CLAIMS: u8 = 1 << 2;
+pub const PAUSE_FUNDING: u8 = 1 << 3;
+pub const PAUSE_NEW_USERS: u8 = 1 << 4;
+pub const PAUSE_ALL: u8 = PAUSE_DEPOSITS | PAUSE_WITHDRAWALS | PAUSE_CLAIMS | PAUSE_FUNDING | PAUSE_NEW_USERS;
 
 pub fn update_rewards(
-    pool: &mut Account<Pool>,
-    user: Option<&mut Box<Account<User>>>,
-    total_staked: u64,
+    pool: &mut Pool,
+    mut user: Option<&mut Box<Account<User>>>,
 ) -> Result<()> {
-    let clock = clock::Clock::get().unwrap();
-    let last_time_reward_applicable =
-        last_time_reward_applicable(pool.reward_duration_end, clock.unix_timestamp);
+    let current_time = current_timestamp()?;
+    let total_weighted_stake = pool.total_weighted_stake;
 
-    pool.reward_per_token_stored = reward_per_token(
-        total_staked,
-        pool.reward_per_token_stored,
-        last_time_reward_applicable,
-        pool.last_update_time,
-        pool.reward_rate,
-    );
+    for (i, stream) in pool.reward_streams.iter_mut().enumerate() {
+        if !stream.is_active() {
+            continue;
+        }
 
-    pool.last_update_time = last_time_reward_applicable;
+        let mut state = stream.state();
+        let mut liability = stream.liability();
+        state
+            .accrue_with_liability(total_weighted_stake, current_time, &mut liability)
+            .map_err(ErrorCode::from)?;
+        stream.set_state(&state);
+        stream.set_liability(&liability);
+
+        if let Some(u) = user.as_mut() {
+            let mut user_state = u.stream_state(i);
+            user_state.settle(stream.reward_per_token_stored).map_err(ErrorCode::from)?;
+            u.set_stream_state(i, &user_state);
+        }
+    }
 
+    //rewards of users staked before the independent policy took effect lock from then on,
+    //new users' lock starts with their first deposit
     if let Some(u) = user {
-        u.reward_per_token_pending = earned(
-            u.balance_staked,
-            pool.reward_per_token_stored,
-            u.reward_per_token_complete,
-            u.reward_per_token_pending,
-        );
-        u.reward_per_token_complete = pool.reward_per_token_stored;
+        if pool.reward_lock_policy == RewardLockPolicy::Independent && u.balance_staked > 0 {
+            u.reward_unlock_time = pool.claim_unlock_time(u);
+        }
     }
-    
+
     Ok(())
 }
 
-pub fn last_time_reward_applicable(reward_duration_end: u64, unix_timestamp: i64) -> u64 {
-    return std::cmp::min(unix_timestamp.try_into().unwrap(), reward_duration_end);
+/// A user's pending rewards per stream as of `current_time`, computed like
+/// `update_rewards` but without mutating the pool or user.
+pub fn pending_rewards(pool: &Pool, user: &User, current_time: u64) -> Result<[u64; MAX_REWARD_STREAMS]> {
+    let mut pending = [0u64; MAX_REWARD_STREAMS];
+    for (i, stream) in pool.reward_streams.iter().enumerate() {
+        if !stream.is_active() {
+            continue;
+        }
+
+        pending[i] = reward_pool_math::pending_reward(
+            &stream.state(),
+            pool.total_weighted_stake,
+            &user.stream_state(i),
+            current_time,
+        )
+        .map_err(ErrorCode::from)?;
+    }
+
+    Ok(pending)
+}
+
+/// Pays out a user's pending reward for a stream, capped to what the vault holds.
+/// Any shortfall stays pending. Returns the amount paid.
+pub fn pay_reward<'info>(
+    user: &mut User,
+    stream_index: usize,
+    reward_vault: &Account<'info, TokenAccount>,
+    reward_account: AccountInfo<'info>,
+    pool_signer: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<u64> {
+    let mut reward_amount = user.reward_per_token_pending[stream_index];
+    if reward_amount > 0 {
+        let vault_balance = reward_vault.amount;
+
+        if vault_balance < reward_amount {
+            reward_amount = vault_balance;
+        }
+        user.reward_per_token_pending[stream_index] = user.reward_per_token_pending[stream_index]
+            .checked_sub(reward_amount)
+            .ok_or(ErrorCode::MathUnderflow)?;
+
+        if reward_amount > 0 {
+            let cpi_ctx = CpiContext::new_with_signer(
+                token_program,
+                token::Transfer {
+                    from: reward_vault.to_account_info(),
+                    to: reward_account,
+                    authority: pool_signer,
+                },
+                signer_seeds,
+            );
+            token::transfer(cpi_ctx, reward_amount)?;
+        }
+    }
+
+    Ok(reward_amount)
+}
+
+/// Fails unless the vault covers what is owed plus the rest of the current period's emissions.
+pub fn check_reward_coverage(stream: &RewardStream, vault_balance: u64, current_time: u64) -> Result<()> {
+    let remaining_emissions = reward_pool_math::emitted_rewards(
+        stream.reward_duration_end,
+        std::cmp::max(current_time, stream.reward_start_time),
+        stream.reward_rate,
+    )
+    .map_err(ErrorCode::from)?;
+    let required = (stream.reward_liability as u128)
+        .checked_add(stream.vesting_outstanding as u128)
+        .and_then(|v| v.checked_add(stream.undistributed as u128))
+        .and_then(|v| v.checked_add(remaining_emissions as u128))
+        .ok_or(ErrorCode::MathOverflow)?;
+    if required > vault_balance as u128 {
+        return Err(ErrorCode::InsufficientRewardFunds.into());
+    }
+    Ok(())
 }
 
-pub fn reward_per_token(
-    total_staked: u64,
-    reward_per_token_stored: u128,
-    last_time_reward_applicable: u64,
-    last_update_time: u64,
-    reward_rate: u64,
-) -> u128 {
-    if total_staked == 0 {
-        return reward_per_token_stored;
+/// The receipt mint and the owner's receipt account, passed in that order as
+/// remaining accounts to instructions changing stake in pools issuing receipts.
+pub fn receipt_accounts<'info>(
+    pool: &Pool,
+    owner: &Pubkey,
+    remaining_accounts: &[AccountInfo<'info>],
+) -> Result<(Account<'info, Mint>, Account<'info, TokenAccount>)> {
+    let (mint_info, account_info) = match remaining_accounts {
+        [mint_info, account_info, ..] => (mint_info, account_info),
+        _ => return Err(ErrorCode::MissingReceiptAccounts.into()),
+    };
+    if *mint_info.key != pool.receipt_mint || *account_info.key != pool.receipt_account(owner) {
+        return Err(ErrorCode::InvalidReceiptAccount.into());
     }
 
-    return reward_per_token_stored
-                .checked_add(
-                    (last_time_reward_applicable as u128)
-                    .checked_sub(last_update_time as u128)
-                    .unwrap()
-                    .checked_mul(reward_rate as u128)
-                    .unwrap()
-                    .checked_mul(PRECISION)
-                    .unwrap()
-                    .checked_div(total_staked as u128)
-                    .unwrap()
-                )
-                .unwrap();
-}
-
-pub fn earned(
-    balance_staked: u64,
-    reward_per_token: u128,
-    user_reward_per_token_paid: u128,
-    user_reward_pending: u64,
-) -> u64 {
-    return (balance_staked as u128)
-        .checked_mul(
-            (reward_per_token as u128)
-                .checked_sub(user_reward_per_token_paid as u128)
-                .unwrap(),
-        )
-        .unwrap()
-        .checked_div(PRECISION)
-        .unwrap()
-        .checked_add(user_reward_pending as u128)
-        .unwrap()
-        .try_into() 
-        .unwrap()
+    Ok((Account::try_from(mint_info)?, Account::try_from(account_info)?))
+}
+
+/// Moves a holder's stake to the receipts they hold, once rewards were updated at
+/// the old balance. Receipts moved away from a holder only count for the recipient
+/// once that holder is synced, so the synced balances never exceed the receipt supply.
+pub fn sync_receipt_balance(
+    pool: &mut Pool,
+    user: &mut User,
+    receipt_balance: u64,
+    receipt_supply: u64,
+    current_time: u64,
+) -> Result<()> {
+    let balance = if receipt_balance > user.balance_staked {
+        let unsynced = receipt_supply.saturating_sub(pool.total_weighted_stake);
+        std::cmp::min(receipt_balance, user.balance_staked.checked_add(unsynced).ok_or(ErrorCode::MathOverflow)?)
+    } else {
+        receipt_balance
+    };
+
+    pool.total_weighted_stake = pool.total_weighted_stake
+        .checked_sub(user.balance_weighted)
+        .ok_or(ErrorCode::MathUnderflow)?
+        .checked_add(balance)
+        .ok_or(ErrorCode::MathOverflow)?;
+    user.set_receipt_balance(balance, current_time);
+    if pool.reward_lock_policy == RewardLockPolicy::Independent && user.reward_unlock_time == 0 && balance > 0 {
+        user.reward_unlock_time = current_time.checked_add(pool.reward_lock_period).ok_or(ErrorCode::MathOverflow)?;
+    }
+
+    Ok(())
+}
+
+/// Current unix time from the clock sysvar, which must not be negative.
+pub fn current_timestamp() -> Result<u64> {
+    let clock = clock::Clock::get()?;
+    Ok(reward_pool_math::to_timestamp(clock.unix_timestamp).map_err(ErrorCode::from)?)
+}
+
+/// Runs an instruction natively, e.g. under `solana-program-test`. Unlike the
+/// generated `entry` it is kept with the `no-entrypoint` feature.
+pub fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    if data.len() < 8 {
+        return Err(anchor_lang::__private::ErrorCode::InstructionMissing.into());
+    }
+    dispatch(program_id, accounts, data)
 }
 
 #[program]
@@ -102,85 +211,385 @@ pub mod reward_pool {
         pool_nonce: u8,
         reward_duration: u64,
         lock_period: u64,
+        config: PoolConfig,
     ) -> Result<()> {
 
         if reward_duration < MIN_DURATION {
             return Err(ErrorCode::DurationTooShort.into());
         }
 
+        config.validate(
+            lock_period,
+            &ctx.accounts.staking_mint.key(),
+            &ctx.accounts.reward_mint.key(),
+        )?;
+
         let pool = &mut ctx.accounts.pool;
 
         pool.authority = ctx.accounts.authority.key();
+        pool.pending_authority = Pubkey::default();
         pool.nonce = pool_nonce;
-        pool.paused = false;
+        pool.pause_flags = 0;
+        pool.guardian = Pubkey::default();
+        pool.emergency = false;
         pool.staking_mint = ctx.accounts.staking_mint.key();
         pool.staking_vault = ctx.accounts.staking_vault.key();
-        pool.reward_mint = ctx.accounts.reward_mint.key();
-        pool.reward_vault = ctx.accounts.reward_vault.key();
         pool.reward_duration = reward_duration;
-        pool.reward_duration_end = 0;
         pool.lock_period = lock_period;
-        pool.last_update_time = 0;
-        pool.reward_rate = 0;
-        pool.reward_per_token_stored = 0;
+        pool.reward_streams = [RewardStream::default(); MAX_REWARD_STREAMS];
+        pool.reward_streams[0] = RewardStream {
+            mint: ctx.accounts.reward_mint.key(),
+            vault: ctx.accounts.reward_vault.key(),
+            ..RewardStream::default()
+        };
         pool.user_stake_count = 0;
+        pool.total_staked = 0;
+        pool.total_weighted_stake = 0;
+        pool.treasury = ctx.accounts.treasury.key();
+        pool.receipt_mint = Pubkey::default();
+        pool.reward_lock_start = if config.reward_lock_policy == RewardLockPolicy::Independent {
+            current_timestamp()?
+        } else {
+            0
+        };
+        pool.apply_config(&config);
+
+        emit!(PoolInitialized {
+            pool: pool.key(),
+            authority: pool.authority,
+            staking_mint: pool.staking_mint,
+            reward_mint: pool.reward_streams[0].mint,
+            reward_duration,
+            lock_period,
+            config,
+            timestamp: current_timestamp()?,
+        });
         
         Ok(())
     }
 
+    /// Changes pool parameters. A new `reward_duration` re-rates any running
+    /// reward period so its remaining rewards emit over the new duration from now.
+    /// A new `lock_period` and `config` only apply to deposits made afterwards.
+    pub fn update_pool_config(
+        ctx: Context<UpdatePoolConfig>,
+        reward_duration: Option<u64>,
+        lock_period: Option<u64>,
+        config: Option<PoolConfig>,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        if let Some(reward_duration) = reward_duration {
+            if reward_duration < MIN_DURATION {
+                return Err(ErrorCode::DurationTooShort.into());
+            }
+
+            update_rewards(
+                pool,
+                None,
+            )?;
+
+            let current_time = current_timestamp()?;
+            for stream in pool.reward_streams.iter_mut() {
+                if !stream.is_active()
+                    || current_time >= stream.reward_duration_end
+                    || current_time < stream.reward_start_time
+                {
+                    continue;
+                }
+
+                let remaining = stream.reward_duration_end.checked_sub(current_time).ok_or(ErrorCode::MathUnderflow)?;
+                let leftover = (remaining as u128).checked_mul(stream.reward_rate).ok_or(ErrorCode::MathOverflow)?;
+
+                stream.reward_rate = reward_pool_math::scaled_reward_rate(0, leftover, reward_duration).map_err(ErrorCode::from)?;
+                stream.reward_start_time = current_time;
+                stream.last_update_time = current_time;
+                stream.reward_duration_end = current_time.checked_add(reward_duration).ok_or(ErrorCode::MathOverflow)?;
+            }
+
+            pool.reward_duration = reward_duration;
+        }
+
+        let lock_period = lock_period.unwrap_or(pool.lock_period);
+        let config = config.unwrap_or_else(|| pool.config());
+        config.validate(
+            lock_period,
+            &pool.staking_mint,
+            &pool.reward_streams[0].mint,
+        )?;
+        if pool.issues_receipts() && (lock_period > 0 || config.max_lock_period > 0) {
+            return Err(ErrorCode::InvalidReceiptConfig.into());
+        }
+        if config.reward_lock_policy == RewardLockPolicy::Independent
+            && pool.reward_lock_policy != RewardLockPolicy::Independent
+        {
+            pool.reward_lock_start = current_timestamp()?;
+        }
+        pool.lock_period = lock_period;
+        pool.apply_config(&config);
+
+        emit!(PoolConfigUpdated {
+            pool: pool.key(),
+            reward_duration: pool.reward_duration,
+            lock_period: pool.lock_period,
+            config,
+        });
+
+        Ok(())
+    }
+
+    /// Reports a user's claimable rewards without changing state, meant to be
+    /// simulated. The result is logged as a `PendingRewards` event and set as
+    /// return data.
+    pub fn view_pending(ctx: Context<ViewPending>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let user = &ctx.accounts.user;
+        let current_time = current_timestamp()?;
+
+        let mut reward_rate = [0u128; MAX_REWARD_STREAMS];
+        let mut reward_duration_end = [0u64; MAX_REWARD_STREAMS];
+        for (i, stream) in pool.reward_streams.iter().enumerate() {
+            reward_rate[i] = stream.reward_rate;
+            reward_duration_end[i] = stream.reward_duration_end;
+        }
+
+        let pending = PendingRewards {
+            pool: pool.key(),
+            user: user.key(),
+            owner: user.owner,
+            pending: pending_rewards(pool, user, current_time)?,
+            reward_rate,
+            reward_duration_end,
+            balance_staked: user.balance_staked,
+            balance_weighted: user.balance_weighted,
+            total_staked: pool.total_staked,
+            total_weighted_stake: pool.total_weighted_stake,
+            claim_unlock_time: pool.claim_unlock_time(user),
+            stake_unlock_time: user.earliest_unlock_time(),
+            timestamp: current_time,
+        };
+        let data = pending.try_to_vec().map_err(|_| ErrorCode::ConversionFailed)?;
+        solana_program::program::set_return_data(&data);
+        emit!(pending);
+
+        Ok(())
+    }
+
     pub fn create_user(ctx: Context<CreateUser>, nonce: u8) -> Result<()> {
         let user = &mut ctx.accounts.user;
         user.pool = *ctx.accounts.pool.to_account_info().key;
         user.owner = *ctx.accounts.owner.key;
-        user.reward_per_token_complete = 0;
-        user.reward_per_token_pending = 0;
+        user.reward_per_token_complete = [0; MAX_REWARD_STREAMS];
+        user.reward_per_token_pending = [0; MAX_REWARD_STREAMS];
         user.balance_staked = 0;
-        user.maturity_time = 0;
+        user.balance_weighted = 0;
+        user.positions = [StakePosition::default(); MAX_STAKE_POSITIONS];
+        user.reward_unlock_time = 0;
         user.nonce = nonce;
 
         let pool = &mut ctx.accounts.pool;
-        pool.user_stake_count = pool.user_stake_count.checked_add(1).unwrap();
+        pool.user_stake_count = pool.user_stake_count.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        emit!(UserCreated {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            owner: ctx.accounts.owner.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn create_vesting(ctx: Context<CreateVesting>, nonce: u8) -> Result<()> {
+        let vesting = &mut ctx.accounts.vesting;
+        vesting.pool = *ctx.accounts.pool.to_account_info().key;
+        vesting.owner = *ctx.accounts.owner.key;
+        vesting.entries = [VestingEntry::default(); MAX_VESTING_ENTRIES];
+        vesting.nonce = nonce;
+
+        emit!(VestingCreated {
+            pool: vesting.pool,
+            vesting: vesting.key(),
+            owner: vesting.owner,
+        });
 
         Ok(())
     }
 
-    pub fn pause(ctx: Context<Pause>) -> Result<()> {
+    /// Pauses the operations in `flags`. Callable by the authority or the guardian.
+    pub fn pause(ctx: Context<Pause>, flags: u8) -> Result<()> {
+        if flags == 0 || flags & !PAUSE_ALL != 0 {
+            return Err(ErrorCode::InvalidPauseFlags.into());
+        }
+
         let pool = &mut ctx.accounts.pool;
-        pool.paused = true;
+        pool.pause_flags |= flags;
+
+        emit!(PoolPaused {
+            pool: pool.key(),
+            pauser: ctx.accounts.pauser.key(),
+            flags,
+            pause_flags: pool.pause_flags,
+            timestamp: current_timestamp()?,
+        });
 
         Ok(())
     }
 
-    pub fn unpause(ctx: Context<Unpause>) -> Result<()> {
+    /// Resumes the operations in `flags`. Only callable by the authority.
+    pub fn unpause(ctx: Context<Unpause>, flags: u8) -> Result<()> {
+        if flags == 0 || flags & !PAUSE_ALL != 0 {
+            return Err(ErrorCode::InvalidPauseFlags.into());
+        }
+
         let pool = &mut ctx.accounts.pool;
-        pool.paused = false;
+        pool.pause_flags &= !flags;
+
+        emit!(PoolUnpaused {
+            pool: pool.key(),
+            flags,
+            pause_flags: pool.pause_flags,
+            timestamp: current_timestamp()?,
+        });
         
         Ok(())
     }
 
-    pub fn stake(ctx: Context<Stake>, amount: u64) -> Result<()> {
+    /// Sets the key allowed to pause the pool, default to remove it.
+    pub fn set_guardian(ctx: Context<SetGuardian>, guardian: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.guardian = guardian;
+
+        emit!(GuardianSet {
+            pool: pool.key(),
+            guardian,
+        });
+
+        Ok(())
+    }
+
+    /// Enables or disables `emergency_withdraw`.
+    pub fn set_emergency(ctx: Context<SetEmergency>, enabled: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.emergency = enabled;
+
+        emit!(EmergencySet {
+            pool: pool.key(),
+            enabled,
+            timestamp: current_timestamp()?,
+        });
+
+        Ok(())
+    }
+
+    /// Makes `stake` mint receipt tokens that `unstake` burns. Receipts can be redeemed
+    /// by whoever holds them, so only pools without lock periods may issue them, and
+    /// only before anything is staked.
+    pub fn enable_receipts(ctx: Context<EnableReceipts>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        if pool.issues_receipts()
+            || pool.total_staked > 0
+            || pool.total_weighted_stake > 0
+            || pool.lock_period > 0
+            || pool.max_lock_period > 0
+        {
+            return Err(ErrorCode::InvalidReceiptConfig.into());
+        }
+
+        pool.receipt_mint = ctx.accounts.receipt_mint.key();
+
+        emit!(ReceiptsEnabled {
+            pool: pool.key(),
+            receipt_mint: pool.receipt_mint,
+        });
+
+        Ok(())
+    }
+
+    /// Moves a holder's stake to the receipts in their associated token account,
+    /// settling rewards earned at the previous balance. Anyone may sync any holder,
+    /// so receipt transfers should sync the sender and then the recipient.
+    pub fn sync_receipt(ctx: Context<SyncReceipt>) -> Result<()> {
+        let user_opt = Some(&mut ctx.accounts.user);
+        update_rewards(
+            &mut ctx.accounts.pool,
+            user_opt,
+        )?;
+
+        //a closed receipt account holds nothing
+        let receipt_balance = if ctx.accounts.receipt_account.data_is_empty() {
+            0
+        } else {
+            let receipt_account: Account<TokenAccount> = Account::try_from(&ctx.accounts.receipt_account)?;
+            receipt_account.amount
+        };
+
+        let current_time = current_timestamp()?;
+        sync_receipt_balance(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.user,
+            receipt_balance,
+            ctx.accounts.receipt_mint.supply,
+            current_time,
+        )?;
+
+        emit!(ReceiptSynced {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            owner: ctx.accounts.user.owner,
+            receipt_balance,
+            balance_staked: ctx.accounts.user.balance_staked,
+            total_weighted_stake: ctx.accounts.pool.total_weighted_stake,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    /// Pools issuing receipts mint them to the owner's receipt account, see `receipt_accounts`.
+    pub fn stake<'info>(ctx: Context<'_, '_, '_, 'info, Stake<'info>>, amount: u64, lock_duration: u64) -> Result<()> {
         if amount == 0 {
             return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
         }
 
         let pool = &mut ctx.accounts.pool;
-        if pool.paused {
+        if pool.is_paused(PAUSE_DEPOSITS) {
             return Err(ErrorCode::PoolPaused.into());
         }
 
-        let total_staked = ctx.accounts.staking_vault.amount;
+        if lock_duration < pool.lock_period || lock_duration > pool.max_lock_period {
+            return Err(ErrorCode::InvalidLockDuration.into());
+        }
 
         let user_opt = Some(&mut ctx.accounts.user);
         update_rewards(
             pool,
             user_opt,
-            total_staked,
-        )
-        .unwrap();
-        
-        let clock = clock::Clock::get().unwrap();
-        ctx.accounts.user.balance_staked = ctx.accounts.user.balance_staked.checked_add(amount).unwrap();
-        ctx.accounts.user.maturity_time = u64::try_from(clock.unix_timestamp).unwrap() + pool.lock_period;
+        )?;
+
+        let current_time = current_timestamp()?;
+        let receipt = if pool.issues_receipts() {
+            let (receipt_mint, receipt_account) = receipt_accounts(pool, ctx.accounts.owner.key, ctx.remaining_accounts)?;
+            sync_receipt_balance(pool, &mut ctx.accounts.user, receipt_account.amount, receipt_mint.supply, current_time)?;
+            Some((receipt_mint, receipt_account))
+        } else {
+            None
+        };
+
+        let weight = pool.lock_weight(amount, lock_duration)?;
+        let unlock_time = current_time.checked_add(lock_duration).ok_or(ErrorCode::MathOverflow)?;
+        ctx.accounts.user.add_position(
+            amount,
+            weight,
+            current_time,
+            unlock_time,
+        )?;
+        ctx.accounts.user.balance_staked = ctx.accounts.user.balance_staked.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        ctx.accounts.user.balance_weighted = ctx.accounts.user.balance_weighted.checked_add(weight).ok_or(ErrorCode::MathOverflow)?;
+        pool.total_staked = pool.total_staked.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        pool.total_weighted_stake = pool.total_weighted_stake.checked_add(weight).ok_or(ErrorCode::MathOverflow)?;
+        if pool.reward_lock_policy == RewardLockPolicy::Independent && ctx.accounts.user.reward_unlock_time == 0 {
+            ctx.accounts.user.reward_unlock_time = current_time.checked_add(pool.reward_lock_period).ok_or(ErrorCode::MathOverflow)?;
+        }
 
         // Transfer tokens into the stake vault.
         {
@@ -195,33 +604,84 @@ pub mod reward_pool {
             token::transfer(cpi_ctx, amount)?;
         }
 
+        // Mint receipts for the deposit.
+        if let Some((receipt_mint, receipt_account)) = receipt {
+            let seeds = &[
+                ctx.accounts.pool.to_account_info().key.as_ref(),
+                &[ctx.accounts.pool.nonce],
+            ];
+            let pool_signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::MintTo {
+                    mint: receipt_mint.to_account_info(),
+                    to: receipt_account.to_account_info(),
+                    authority: ctx.accounts.pool_signer.to_account_info(),
+                },
+                pool_signer,
+            );
+            token::mint_to(cpi_ctx, amount)?;
+        }
+
+        emit!(Staked {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            owner: ctx.accounts.owner.key(),
+            amount,
+            weight,
+            unlock_time,
+            balance_staked: ctx.accounts.user.balance_staked,
+            total_staked: ctx.accounts.pool.total_staked,
+            timestamp: current_time,
+        });
+
         Ok(())
     }
 
-    pub fn unstake(ctx: Context<Stake>, spt_amount: u64) -> Result<()> {
+    /// Pools issuing receipts burn them from the owner's receipt account, see `receipt_accounts`.
+    pub fn unstake<'info>(ctx: Context<'_, '_, '_, 'info, Stake<'info>>, spt_amount: u64) -> Result<()> {
         if spt_amount == 0 {
             return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
         }
 
-        let clock = clock::Clock::get().unwrap();
-        if ctx.accounts.user.maturity_time > u64::try_from(clock.unix_timestamp).unwrap() {
-            return Err(ErrorCode::CannotStakeOrClaimBeforeMaturity.into());
-        }
-
-        let total_staked = ctx.accounts.staking_vault.amount;
-        
-        if ctx.accounts.user.balance_staked < spt_amount {
-            return Err(ErrorCode::InsufficientFundUnstake.into());
+        if ctx.accounts.pool.is_paused(PAUSE_WITHDRAWALS) {
+            return Err(ErrorCode::PoolPaused.into());
         }
 
+        let current_time = current_timestamp()?;
         let user_opt = Some(&mut ctx.accounts.user);
         update_rewards(
             &mut ctx.accounts.pool,
             user_opt,
-            total_staked,
-        )
-        .unwrap();
-        ctx.accounts.user.balance_staked = ctx.accounts.user.balance_staked.checked_sub(spt_amount).unwrap();
+        )?;
+        let receipt = if ctx.accounts.pool.issues_receipts() {
+            let (receipt_mint, receipt_account) = receipt_accounts(&ctx.accounts.pool, ctx.accounts.owner.key, ctx.remaining_accounts)?;
+            sync_receipt_balance(
+                &mut ctx.accounts.pool,
+                &mut ctx.accounts.user,
+                receipt_account.amount,
+                receipt_mint.supply,
+                current_time,
+            )?;
+            Some((receipt_mint, receipt_account))
+        } else {
+            None
+        };
+
+        if ctx.accounts.user.balance_staked < spt_amount {
+            return Err(ErrorCode::InsufficientFundUnstake.into());
+        }
+
+        if ctx.accounts.user.matured_balance(current_time)? < spt_amount {
+            return Err(ErrorCode::CannotStakeOrClaimBeforeMaturity.into());
+        }
+
+        let weight = ctx.accounts.user.consume_matured_positions(spt_amount, current_time)?;
+        ctx.accounts.user.balance_staked = ctx.accounts.user.balance_staked.checked_sub(spt_amount).ok_or(ErrorCode::MathUnderflow)?;
+        ctx.accounts.user.balance_weighted = ctx.accounts.user.balance_weighted.checked_sub(weight).ok_or(ErrorCode::MathUnderflow)?;
+        ctx.accounts.pool.total_staked = ctx.accounts.pool.total_staked.checked_sub(spt_amount).ok_or(ErrorCode::MathUnderflow)?;
+        ctx.accounts.pool.total_weighted_stake = ctx.accounts.pool.total_weighted_stake.checked_sub(weight).ok_or(ErrorCode::MathUnderflow)?;
 
         // Transfer tokens from the pool vault to user vault.
         {
@@ -240,104 +700,196 @@ pub mod reward_pool {
                 },
                 pool_signer,
             );
-            token::transfer(cpi_ctx, spt_amount.try_into().unwrap())?;
+            token::transfer(cpi_ctx, spt_amount)?;
+        }
+
+        // Burn the redeemed receipts.
+        if let Some((receipt_mint, receipt_account)) = receipt {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Burn {
+                    mint: receipt_mint.to_account_info(),
+                    to: receipt_account.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            );
+            token::burn(cpi_ctx, spt_amount)?;
         }
 
+        emit!(Unstaked {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            owner: ctx.accounts.owner.key(),
+            amount: spt_amount,
+            weight,
+            penalty: 0,
+            balance_staked: ctx.accounts.user.balance_staked,
+            total_staked: ctx.accounts.pool.total_staked,
+            timestamp: current_time,
+        });
+
         Ok(())
     }
 
-    pub fn authorize_funder(ctx: Context<FunderChange>, funder_to_add: Pubkey) -> Result<()> {
-        if funder_to_add == ctx.accounts.pool.authority.key() {
-            return Err(ErrorCode::FunderAlreadyAuthorized.into());
-        }
-        let funders = &mut ctx.accounts.pool.funders;
-        if funders.iter().any(|x| *x == funder_to_add) {
-            return Err(ErrorCode::FunderAlreadyAuthorized.into());
+    /// Returns the user's whole stake regardless of locks, forfeiting their rewards
+    /// to the pool. Only available while the pool is in emergency mode. Pools
+    /// issuing receipts only return the stake still backed by the owner's receipts.
+    pub fn emergency_withdraw<'info>(ctx: Context<'_, '_, '_, 'info, Stake<'info>>) -> Result<()> {
+        if !ctx.accounts.pool.emergency {
+            return Err(ErrorCode::EmergencyNotEnabled.into());
         }
-        let default_pubkey = Pubkey::default();
-        if let Some(idx) = funders.iter().position(|x| *x == default_pubkey) {
-            funders[idx] = funder_to_add;
+
+        let receipt = if ctx.accounts.pool.issues_receipts() {
+            Some(receipt_accounts(&ctx.accounts.pool, ctx.accounts.owner.key, ctx.remaining_accounts)?)
         } else {
-            return Err(ErrorCode::MaxFunders.into());
+            None
+        };
+        let amount = match &receipt {
+            Some((_, receipt_account)) => std::cmp::min(ctx.accounts.user.balance_staked, receipt_account.amount),
+            None => ctx.accounts.user.balance_staked,
+        };
+        if amount == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
         }
-        Ok(())
-    }
 
-    pub fn deauthorize_funder(ctx: Context<FunderChange>, funder_to_remove: Pubkey) -> Result<()> {
-        if funder_to_remove == ctx.accounts.pool.authority.key() {
-            return Err(ErrorCode::CannotDeauthorizePoolAuthority.into());
-        }
-        let funders = &mut ctx.accounts.pool.funders;
-        if let Some(idx) = funders.iter().position(|x| *x == funder_to_remove) {
-            funders[idx] = Pubkey::default();
-        } else {
-            return Err(ErrorCode::CannotDeauthorizeMissingAuthority.into());
+        // settle emissions up to now on a copy, keeping the pool as is if that fails
+        let mut settled = (**ctx.accounts.pool).clone();
+        if update_rewards(&mut settled, None).is_ok() {
+            **ctx.accounts.pool = settled;
         }
-        Ok(())
-    }
 
-    pub fn fund(ctx: Context<Fund>, amount: u64) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
-        let total_staked = ctx.accounts.staking_vault.amount;
-
-        update_rewards(
-            pool,
-            None,
-            total_staked,
-        )
-        .unwrap();
-
-        let current_time = clock::Clock::get().unwrap().unix_timestamp.try_into().unwrap();
-        let reward_period_end = pool.reward_duration_end;
+        let user = &mut ctx.accounts.user;
+        for (i, stream) in pool.reward_streams.iter_mut().enumerate() {
+            if !stream.is_active() {
+                continue;
+            }
+            // forfeited rewards go back to the pool, best effort as this must never fail
+            let accrued = stream.reward_per_token_stored
+                .checked_sub(user.reward_per_token_complete[i])
+                .and_then(|delta| delta.checked_mul(user.balance_weighted as u128))
+                .map(|v| v / PRECISION)
+                .and_then(|v| u64::try_from(v).ok())
+                .unwrap_or(0);
+            let forfeited = user.reward_per_token_pending[i].saturating_add(accrued);
+            let forfeited = forfeited.min(stream.reward_liability);
+            stream.reward_liability -= forfeited;
+            stream.undistributed = stream.undistributed.saturating_add(forfeited);
+            user.reward_per_token_complete[i] = stream.reward_per_token_stored;
+            user.reward_per_token_pending[i] = 0;
+        }
+        pool.total_staked = pool.total_staked.saturating_sub(amount);
+        pool.total_weighted_stake = pool.total_weighted_stake.saturating_sub(user.balance_weighted);
+        user.balance_staked = 0;
+        user.balance_weighted = 0;
+        user.positions = [StakePosition::default(); MAX_STAKE_POSITIONS];
+        user.reward_unlock_time = 0;
 
-        if current_time >= reward_period_end {
-            pool.reward_rate = amount.checked_div(pool.reward_duration).unwrap();
-        } else {
-            let remaining = pool.reward_duration_end.checked_sub(current_time).unwrap();
-            let leftover = remaining.checked_mul(pool.reward_rate).unwrap();
+        // Transfer tokens from the pool vault to user vault.
+        {
+            let seeds = &[
+                ctx.accounts.pool.to_account_info().key.as_ref(),
+                &[ctx.accounts.pool.nonce],
+            ];
+            let pool_signer = &[&seeds[..]];
 
-            pool.reward_rate = amount
-                .checked_add(leftover)
-                .unwrap()
-                .checked_div(pool.reward_duration)
-                .unwrap();
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.staking_vault.to_account_info(),
+                    to: ctx.accounts.stake_from_account.to_account_info(),
+                    authority: ctx.accounts.pool_signer.to_account_info(),
+                },
+                pool_signer,
+            );
+            token::transfer(cpi_ctx, amount)?;
         }
 
-        // Transfer reward A tokens into the A vault.
-        if amount > 0 {
+        // Burn the redeemed receipts.
+        if let Some((receipt_mint, receipt_account)) = receipt {
             let cpi_ctx = CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
-                    from: ctx.accounts.from.to_account_info(),
-                    to: ctx.accounts.reward_vault.to_account_info(),
-                    authority: ctx.accounts.funder.to_account_info(),
+                token::Burn {
+                    mint: receipt_mint.to_account_info(),
+                    to: receipt_account.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
                 },
             );
-
-            token::transfer(cpi_ctx, amount)?;
+            token::burn(cpi_ctx, amount)?;
         }
 
-        pool.last_update_time = current_time;
-        pool.reward_duration_end = current_time.checked_add(pool.reward_duration).unwrap();
+        emit!(EmergencyWithdrawn {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            owner: ctx.accounts.owner.key(),
+            amount,
+            timestamp: current_timestamp()?,
+        });
 
         Ok(())
     }
 
-    pub fn claim(ctx: Context<ClaimReward>) -> Result<()> {
-        let total_staked = ctx.accounts.staking_vault.amount;
+    pub fn early_unstake(ctx: Context<EarlyUnstake>, spt_amount: u64) -> Result<()> {
+        if spt_amount == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+        }
 
-        let clock = clock::Clock::get().unwrap();
-        if ctx.accounts.user.maturity_time > u64::try_from(clock.unix_timestamp).unwrap() {
-            return Err(ErrorCode::CannotStakeOrClaimBeforeMaturity.into());
+        if ctx.accounts.pool.is_paused(PAUSE_WITHDRAWALS) {
+            return Err(ErrorCode::PoolPaused.into());
+        }
+
+        if ctx.accounts.pool.penalty_curve == PenaltyCurve::Disabled {
+            return Err(ErrorCode::EarlyUnstakeDisabled.into());
+        }
+
+        if ctx.accounts.pool.issues_receipts() {
+            return Err(ErrorCode::ReceiptsUnsupported.into());
+        }
+
+        if ctx.accounts.user.balance_staked < spt_amount {
+            return Err(ErrorCode::InsufficientFundUnstake.into());
         }
 
         let user_opt = Some(&mut ctx.accounts.user);
         update_rewards(
             &mut ctx.accounts.pool,
             user_opt,
-            total_staked,
-        )
-        .unwrap();
+        )?;
+
+        let current_time = current_timestamp()?;
+        let (penalty, weight) = ctx.accounts.user.consume_positions_early(
+            spt_amount,
+            current_time,
+            &ctx.accounts.pool,
+        )?;
+        ctx.accounts.user.balance_staked = ctx.accounts.user.balance_staked.checked_sub(spt_amount).ok_or(ErrorCode::MathUnderflow)?;
+        ctx.accounts.user.balance_weighted = ctx.accounts.user.balance_weighted.checked_sub(weight).ok_or(ErrorCode::MathUnderflow)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.total_staked = pool.total_staked.checked_sub(spt_amount).ok_or(ErrorCode::MathUnderflow)?;
+        pool.total_weighted_stake = pool.total_weighted_stake.checked_sub(weight).ok_or(ErrorCode::MathUnderflow)?;
+
+        //the unstaker's remaining stake takes no share of their own penalty
+        let others_weighted_stake = pool.total_weighted_stake
+            .checked_sub(ctx.accounts.user.balance_weighted)
+            .ok_or(ErrorCode::MathUnderflow)?;
+        if penalty > 0 && pool.penalty_destination == PenaltyDestination::Stakers {
+            let stream = &mut pool.reward_streams[0];
+            if others_weighted_stake > 0 {
+                stream.reward_liability = stream.reward_liability.checked_add(penalty).ok_or(ErrorCode::MathOverflow)?;
+                let increment = (penalty as u128)
+                    .checked_mul(PRECISION)
+                    .ok_or(ErrorCode::MathOverflow)?
+                    / others_weighted_stake as u128;
+                stream.reward_per_token_stored = stream.reward_per_token_stored
+                    .checked_add(increment)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                //settled above, so skipping the increment leaves earlier rewards intact
+                ctx.accounts.user.reward_per_token_complete[0] = stream.reward_per_token_stored;
+            } else {
+                stream.undistributed = stream.undistributed.checked_add(penalty).ok_or(ErrorCode::MathOverflow)?;
+            }
+        }
 
         let seeds = &[
             ctx.accounts.pool.to_account_info().key.as_ref(),
@@ -345,476 +897,2579 @@ pub mod reward_pool {
         ];
         let pool_signer = &[&seeds[..]];
 
-        if ctx.accounts.user.reward_per_token_pending > 0 {
-            let mut reward_amount = ctx.accounts.user.reward_per_token_pending;
-            let vault_balance = ctx.accounts.reward_vault.amount;
-
-            ctx.accounts.user.reward_per_token_pending = 0;
-            if vault_balance < reward_amount {
-                reward_amount = vault_balance;
-            }
-
-            if reward_amount > 0 {
-                let cpi_ctx = CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    token::Transfer {
-                        from: ctx.accounts.reward_vault.to_account_info(),
-                        to: ctx.accounts.reward_account.to_account_info(),
-                        authority: ctx.accounts.pool_signer.to_account_info(),
-                    },
-                    pool_signer,
-                );
-                token::transfer(cpi_ctx, reward_amount)?;
-            }
-        }
-        Ok(())
-    }
+        // Transfer the penalty to remaining stakers or the treasury.
+        if penalty > 0 {
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.staking_vault.to_account_info(),
+                    to: ctx.accounts.penalty_vault.to_account_info(),
+                    authority: ctx.accounts.pool_signer.to_account_info(),
+                },
+                pool_signer,
+            );
+            token::transfer(cpi_ctx, penalty)?;
+        }
+
+        // Transfer the remainder from the pool vault to user vault.
+        let withdraw_amount = spt_amount.checked_sub(penalty).ok_or(ErrorCode::MathUnderflow)?;
+        if withdraw_amount > 0 {
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.staking_vault.to_account_info(),
+                    to: ctx.accounts.stake_from_account.to_account_info(),
+                    authority: ctx.accounts.pool_signer.to_account_info(),
+                },
+                pool_signer,
+            );
+            token::transfer(cpi_ctx, withdraw_amount)?;
+        }
+
+        emit!(Unstaked {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            owner: ctx.accounts.owner.key(),
+            amount: spt_amount,
+            weight,
+            penalty,
+            balance_staked: ctx.accounts.user.balance_staked,
+            total_staked: ctx.accounts.pool.total_staked,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn sweep_excess_stake(ctx: Context<SweepExcessStake>) -> Result<()> {
+        let excess = ctx.accounts.staking_vault.amount
+            .checked_sub(ctx.accounts.pool.total_staked)
+            .ok_or(ErrorCode::MathUnderflow)?;
+        if excess == 0 {
+            return Err(ErrorCode::NoExcessStake.into());
+        }
+
+        // Transfer tokens sent directly to the stake vault out to the authority.
+        {
+            let seeds = &[
+                ctx.accounts.pool.to_account_info().key.as_ref(),
+                &[ctx.accounts.pool.nonce],
+            ];
+            let pool_signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.staking_vault.to_account_info(),
+                    to: ctx.accounts.destination.to_account_info(),
+                    authority: ctx.accounts.pool_signer.to_account_info(),
+                },
+                pool_signer,
+            );
+            token::transfer(cpi_ctx, excess)?;
+        }
+
+        emit!(ExcessStakeSwept {
+            pool: ctx.accounts.pool.key(),
+            destination: ctx.accounts.destination.key(),
+            amount: excess,
+        });
+
+        Ok(())
+    }
+
+    /// Transfers a stream's undistributed rewards out instead of rolling them into the next funding.
+    pub fn recover_undistributed(ctx: Context<RecoverUndistributed>, stream_index: u8) -> Result<()> {
+        update_rewards(
+            &mut ctx.accounts.pool,
+            None,
+        )?;
+
+        let stream = &mut ctx.accounts.pool.reward_streams[stream_index as usize];
+        let amount = std::cmp::min(stream.undistributed, ctx.accounts.reward_vault.amount);
+        if amount == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+        }
+        stream.undistributed = stream.undistributed.checked_sub(amount).ok_or(ErrorCode::MathUnderflow)?;
+
+        // Transfer undistributed rewards to the authority.
+        {
+            let seeds = &[
+                ctx.accounts.pool.to_account_info().key.as_ref(),
+                &[ctx.accounts.pool.nonce],
+            ];
+            let pool_signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.reward_vault.to_account_info(),
+                    to: ctx.accounts.destination.to_account_info(),
+                    authority: ctx.accounts.pool_signer.to_account_info(),
+                },
+                pool_signer,
+            );
+            token::transfer(cpi_ctx, amount)?;
+        }
+
+        emit!(UndistributedRecovered {
+            pool: ctx.accounts.pool.key(),
+            stream_index,
+            destination: ctx.accounts.destination.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Pubkey) -> Result<()> {
+        if new_authority == Pubkey::default() || new_authority == ctx.accounts.pool.authority {
+            return Err(ErrorCode::InvalidNewAuthority.into());
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        pool.pending_authority = new_authority;
+
+        emit!(AuthorityProposed {
+            pool: pool.key(),
+            authority: pool.authority,
+            pending_authority: new_authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let previous_authority = pool.authority;
+        pool.authority = pool.pending_authority;
+        pool.pending_authority = Pubkey::default();
+
+        emit!(AuthorityAccepted {
+            pool: pool.key(),
+            previous_authority,
+            authority: pool.authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn cancel_authority_transfer(ctx: Context<ProposeAuthority>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        if pool.pending_authority == Pubkey::default() {
+            return Err(ErrorCode::NoPendingAuthority.into());
+        }
+        let pending_authority = pool.pending_authority;
+        pool.pending_authority = Pubkey::default();
+
+        emit!(AuthorityTransferCancelled {
+            pool: pool.key(),
+            authority: pool.authority,
+            pending_authority,
+        });
+
+        Ok(())
+    }
+
+    pub fn authorize_funder(ctx: Context<FunderChange>, funder_to_add: Pubkey) -> Result<()> {
+        if funder_to_add == ctx.accounts.pool.authority.key() {
+            return Err(ErrorCode::FunderAlreadyAuthorized.into());
+        }
+        let funders = &mut ctx.accounts.pool.funders;
+        if funders.contains(&funder_to_add) {
+            return Err(ErrorCode::FunderAlreadyAuthorized.into());
+        }
+        let default_pubkey = Pubkey::default();
+        if let Some(idx) = funders.iter().position(|x| *x == default_pubkey) {
+            funders[idx] = funder_to_add;
+        } else {
+            return Err(ErrorCode::MaxFunders.into());
+        }
+
+        emit!(FunderAuthorized {
+            pool: ctx.accounts.pool.key(),
+            funder: funder_to_add,
+        });
+        Ok(())
+    }
+
+    pub fn deauthorize_funder(ctx: Context<FunderChange>, funder_to_remove: Pubkey) -> Result<()> {
+        if funder_to_remove == ctx.accounts.pool.authority.key() {
+            return Err(ErrorCode::CannotDeauthorizePoolAuthority.into());
+        }
+        let funders = &mut ctx.accounts.pool.funders;
+        if let Some(idx) = funders.iter().position(|x| *x == funder_to_remove) {
+            funders[idx] = Pubkey::default();
+        } else {
+            return Err(ErrorCode::CannotDeauthorizeMissingAuthority.into());
+        }
+
+        emit!(FunderDeauthorized {
+            pool: ctx.accounts.pool.key(),
+            funder: funder_to_remove,
+        });
+        Ok(())
+    }
+
+    pub fn add_reward_stream(ctx: Context<AddRewardStream>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let reward_mint = ctx.accounts.reward_mint.key();
+
+        if pool.reward_streams.iter().any(|x| x.is_active() && x.mint == reward_mint) {
+            return Err(ErrorCode::RewardStreamAlreadyExists.into());
+        }
+
+        let idx = match pool.reward_streams.iter().position(|x| !x.is_active()) {
+            Some(idx) => idx,
+            None => return Err(ErrorCode::MaxRewardStreams.into()),
+        };
+        pool.reward_streams[idx] = RewardStream {
+            mint: reward_mint,
+            vault: ctx.accounts.reward_vault.key(),
+            ..RewardStream::default()
+        };
+
+        emit!(RewardStreamAdded {
+            pool: pool.key(),
+            stream_index: idx as u8,
+            mint: reward_mint,
+            vault: ctx.accounts.reward_vault.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn fund(ctx: Context<Fund>, stream_index: u8, amount: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        update_rewards(
+            pool,
+            None,
+        )?;
+
+        let current_time = current_timestamp()?;
+        let reward_duration = pool.reward_duration;
+        let stream = &mut pool.reward_streams[stream_index as usize];
+
+        if stream.reward_start_time > current_time {
+            return Err(ErrorCode::RewardPeriodScheduled.into());
+        }
+
+        //roll rewards nobody accrued into the new period
+        let total_amount = amount.checked_add(stream.undistributed).ok_or(ErrorCode::MathOverflow)?;
+        stream.undistributed = 0;
+
+        let mut state = stream.state();
+        state.fund(total_amount, reward_duration, current_time).map_err(ErrorCode::from)?;
+        stream.set_state(&state);
+
+        // Transfer reward A tokens into the A vault.
+        if amount > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.from.to_account_info(),
+                    to: ctx.accounts.reward_vault.to_account_info(),
+                    authority: ctx.accounts.funder.to_account_info(),
+                },
+            );
+
+            token::transfer(cpi_ctx, amount)?;
+        }
+
+        let stream = &pool.reward_streams[stream_index as usize];
+        check_reward_coverage(
+            stream,
+            ctx.accounts.reward_vault.amount.checked_add(amount).ok_or(ErrorCode::MathOverflow)?,
+            current_time,
+        )?;
+
+        emit!(PoolFunded {
+            pool: pool.key(),
+            funder: ctx.accounts.funder.key(),
+            stream_index,
+            amount,
+            reward_rate: stream.reward_rate,
+            reward_per_token_stored: stream.reward_per_token_stored,
+            reward_start_time: stream.reward_start_time,
+            reward_duration_end: stream.reward_duration_end,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    /// Funds a stream for a campaign emitting linearly between `start_ts` and `end_ts`.
+    /// The stream must not have a running or scheduled period.
+    pub fn fund_scheduled(
+        ctx: Context<Fund>,
+        stream_index: u8,
+        amount: u64,
+        start_ts: u64,
+        end_ts: u64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+
+        update_rewards(
+            pool,
+            None,
+        )?;
+
+        let current_time = current_timestamp()?;
+        if start_ts < current_time || end_ts <= start_ts {
+            return Err(ErrorCode::InvalidRewardSchedule.into());
+        }
+        let duration = end_ts.checked_sub(start_ts).ok_or(ErrorCode::MathUnderflow)?;
+        if duration < MIN_DURATION {
+            return Err(ErrorCode::DurationTooShort.into());
+        }
+
+        let stream = &mut pool.reward_streams[stream_index as usize];
+        if current_time < stream.reward_duration_end {
+            return Err(ErrorCode::RewardPeriodActive.into());
+        }
+
+        let total_amount = amount.checked_add(stream.undistributed).ok_or(ErrorCode::MathOverflow)?;
+        stream.undistributed = 0;
+        stream.reward_rate = reward_pool_math::scaled_reward_rate(total_amount, 0, duration).map_err(ErrorCode::from)?;
+        stream.reward_start_time = start_ts;
+        stream.last_update_time = start_ts;
+        stream.reward_duration_end = end_ts;
+
+        check_reward_coverage(
+            stream,
+            ctx.accounts.reward_vault.amount.checked_add(amount).ok_or(ErrorCode::MathOverflow)?,
+            current_time,
+        )?;
+
+        // Transfer reward tokens into the vault.
+        if amount > 0 {
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.from.to_account_info(),
+                    to: ctx.accounts.reward_vault.to_account_info(),
+                    authority: ctx.accounts.funder.to_account_info(),
+                },
+            );
+
+            token::transfer(cpi_ctx, amount)?;
+        }
+
+        let stream = &pool.reward_streams[stream_index as usize];
+        emit!(PoolFunded {
+            pool: pool.key(),
+            funder: ctx.accounts.funder.key(),
+            stream_index,
+            amount,
+            reward_rate: stream.reward_rate,
+            reward_per_token_stored: stream.reward_per_token_stored,
+            reward_start_time: stream.reward_start_time,
+            reward_duration_end: stream.reward_duration_end,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn claim(ctx: Context<ClaimReward>, stream_index: u8) -> Result<()> {
+        if ctx.accounts.pool.is_paused(PAUSE_CLAIMS) {
+            return Err(ErrorCode::PoolPaused.into());
+        }
+
+        if ctx.accounts.pool.vesting_period > 0 {
+            return Err(ErrorCode::ClaimMustVest.into());
+        }
+
+        let current_time = current_timestamp()?;
+        if ctx.accounts.pool.claim_unlock_time(&ctx.accounts.user) > current_time {
+            return Err(ErrorCode::CannotStakeOrClaimBeforeMaturity.into());
+        }
+
+        let user_opt = Some(&mut ctx.accounts.user);
+        update_rewards(
+            &mut ctx.accounts.pool,
+            user_opt,
+        )?;
+
+        let seeds = &[
+            ctx.accounts.pool.to_account_info().key.as_ref(),
+            &[ctx.accounts.pool.nonce],
+        ];
+        let pool_signer = &[&seeds[..]];
+
+        let paid = pay_reward(
+            &mut ctx.accounts.user,
+            stream_index as usize,
+            &ctx.accounts.reward_vault,
+            ctx.accounts.reward_account.to_account_info(),
+            ctx.accounts.pool_signer.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+            pool_signer,
+        )?;
+        let stream = &mut ctx.accounts.pool.reward_streams[stream_index as usize];
+        stream.reward_liability = stream.reward_liability.checked_sub(paid).ok_or(ErrorCode::MathUnderflow)?;
+
+        emit!(RewardPaid {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            owner: ctx.accounts.owner.key(),
+            stream_index,
+            amount: paid,
+            pending: ctx.accounts.user.reward_per_token_pending[stream_index as usize],
+            reward_per_token_stored: ctx.accounts.pool.reward_streams[stream_index as usize].reward_per_token_stored,
+            timestamp: current_time,
+        });
+
+        if ctx.accounts.pool.reward_lock_policy == RewardLockPolicy::Independent {
+            ctx.accounts.user.reward_unlock_time = current_time
+                .checked_add(ctx.accounts.pool.reward_lock_period)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Claims every active reward stream. Remaining accounts are expected as
+    /// (reward vault, reward account) pairs, one pair per active stream in order.
+    pub fn claim_all<'info>(ctx: Context<'_, '_, '_, 'info, ClaimAll<'info>>) -> Result<()> {
+        if ctx.accounts.pool.is_paused(PAUSE_CLAIMS) {
+            return Err(ErrorCode::PoolPaused.into());
+        }
+
+        if ctx.accounts.pool.vesting_period > 0 {
+            return Err(ErrorCode::ClaimMustVest.into());
+        }
+
+        let current_time = current_timestamp()?;
+        if ctx.accounts.pool.claim_unlock_time(&ctx.accounts.user) > current_time {
+            return Err(ErrorCode::CannotStakeOrClaimBeforeMaturity.into());
+        }
+
+        let user_opt = Some(&mut ctx.accounts.user);
+        update_rewards(
+            &mut ctx.accounts.pool,
+            user_opt,
+        )?;
+
+        let seeds = &[
+            ctx.accounts.pool.to_account_info().key.as_ref(),
+            &[ctx.accounts.pool.nonce],
+        ];
+        let pool_signer = &[&seeds[..]];
+
+        let mut remaining = ctx.remaining_accounts.iter();
+        for i in 0..MAX_REWARD_STREAMS {
+            let stream = ctx.accounts.pool.reward_streams[i];
+            if !stream.is_active() {
+                continue;
+            }
+
+            let (vault_info, reward_account) = match (remaining.next(), remaining.next()) {
+                (Some(vault_info), Some(reward_account)) => (vault_info, reward_account),
+                _ => return Err(ErrorCode::MissingRewardStreamAccounts.into()),
+            };
+            if *vault_info.key != stream.vault {
+                return Err(ErrorCode::InvalidRewardStream.into());
+            }
+            let reward_vault: Account<TokenAccount> = Account::try_from(vault_info)?;
+
+            let paid = pay_reward(
+                &mut ctx.accounts.user,
+                i,
+                &reward_vault,
+                reward_account.clone(),
+                ctx.accounts.pool_signer.to_account_info(),
+                ctx.accounts.token_program.to_account_info(),
+                pool_signer,
+            )?;
+            let stream = &mut ctx.accounts.pool.reward_streams[i];
+            stream.reward_liability = stream.reward_liability.checked_sub(paid).ok_or(ErrorCode::MathUnderflow)?;
+
+            emit!(RewardPaid {
+                pool: ctx.accounts.pool.key(),
+                user: ctx.accounts.user.key(),
+                owner: ctx.accounts.owner.key(),
+                stream_index: i as u8,
+                amount: paid,
+                pending: ctx.accounts.user.reward_per_token_pending[i],
+                reward_per_token_stored: ctx.accounts.pool.reward_streams[i].reward_per_token_stored,
+                timestamp: current_time,
+            });
+        }
+
+        if ctx.accounts.pool.reward_lock_policy == RewardLockPolicy::Independent {
+            ctx.accounts.user.reward_unlock_time = current_time
+                .checked_add(ctx.accounts.pool.reward_lock_period)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Moves pending rewards of a stream into a vesting entry in vesting pools.
+    /// The tokens stay in the reward vault until withdrawn with `withdraw_vested`.
+    pub fn claim_vesting(ctx: Context<ClaimVesting>, stream_index: u8) -> Result<()> {
+        if ctx.accounts.pool.is_paused(PAUSE_CLAIMS) {
+            return Err(ErrorCode::PoolPaused.into());
+        }
+
+        if ctx.accounts.pool.vesting_period == 0 {
+            return Err(ErrorCode::VestingDisabled.into());
+        }
+
+        let current_time = current_timestamp()?;
+        if ctx.accounts.pool.claim_unlock_time(&ctx.accounts.user) > current_time {
+            return Err(ErrorCode::CannotStakeOrClaimBeforeMaturity.into());
+        }
+
+        let user_opt = Some(&mut ctx.accounts.user);
+        update_rewards(
+            &mut ctx.accounts.pool,
+            user_opt,
+        )?;
+
+        let pool = &mut ctx.accounts.pool;
+        let user = &mut ctx.accounts.user;
+        let amount = user.reward_per_token_pending[stream_index as usize];
+        if amount == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+        }
+
+        ctx.accounts.vesting.add_entry(VestingEntry {
+            stream_index,
+            amount,
+            withdrawn: 0,
+            start_time: current_time,
+            cliff_time: current_time.checked_add(pool.vesting_cliff).ok_or(ErrorCode::MathOverflow)?,
+            end_time: current_time.checked_add(pool.vesting_period).ok_or(ErrorCode::MathOverflow)?,
+        })?;
+        user.reward_per_token_pending[stream_index as usize] = 0;
+
+        let stream = &mut pool.reward_streams[stream_index as usize];
+        stream.reward_liability = stream.reward_liability.checked_sub(amount).ok_or(ErrorCode::MathUnderflow)?;
+        stream.vesting_outstanding = stream.vesting_outstanding.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        if pool.reward_lock_policy == RewardLockPolicy::Independent {
+            user.reward_unlock_time = current_time.checked_add(pool.reward_lock_period).ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        emit!(RewardVested {
+            pool: pool.key(),
+            user: user.key(),
+            owner: ctx.accounts.owner.key(),
+            stream_index,
+            amount,
+            cliff_time: current_time.checked_add(pool.vesting_cliff).ok_or(ErrorCode::MathOverflow)?,
+            end_time: current_time.checked_add(pool.vesting_period).ok_or(ErrorCode::MathOverflow)?,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    /// Releases the unlocked part of a stream's vesting entries.
+    pub fn withdraw_vested(ctx: Context<WithdrawVested>, stream_index: u8) -> Result<()> {
+        if ctx.accounts.pool.is_paused(PAUSE_CLAIMS) {
+            return Err(ErrorCode::PoolPaused.into());
+        }
+
+        let current_time = current_timestamp()?;
+
+        let releasable = std::cmp::min(
+            ctx.accounts.vesting.releasable(stream_index, current_time)?,
+            ctx.accounts.reward_vault.amount,
+        );
+        if releasable == 0 {
+            return Err(ErrorCode::NothingVested.into());
+        }
+
+        ctx.accounts.vesting.release(stream_index, releasable, current_time)?;
+        let stream = &mut ctx.accounts.pool.reward_streams[stream_index as usize];
+        stream.vesting_outstanding = stream.vesting_outstanding.checked_sub(releasable).ok_or(ErrorCode::MathUnderflow)?;
+
+        // Transfer vested rewards from the reward vault to the user.
+        {
+            let seeds = &[
+                ctx.accounts.pool.to_account_info().key.as_ref(),
+                &[ctx.accounts.pool.nonce],
+            ];
+            let pool_signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.reward_vault.to_account_info(),
+                    to: ctx.accounts.reward_account.to_account_info(),
+                    authority: ctx.accounts.pool_signer.to_account_info(),
+                },
+                pool_signer,
+            );
+            token::transfer(cpi_ctx, releasable)?;
+        }
+
+        emit!(VestedWithdrawn {
+            pool: ctx.accounts.pool.key(),
+            vesting: ctx.accounts.vesting.key(),
+            owner: ctx.accounts.owner.key(),
+            stream_index,
+            amount: releasable,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    /// Restakes pending rewards of a stream paying out in the staking mint.
+    pub fn compound(ctx: Context<Compound>, stream_index: u8) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        if pool.is_any_paused(PAUSE_DEPOSITS | PAUSE_CLAIMS) {
+            return Err(ErrorCode::PoolPaused.into());
+        }
+
+        if pool.reward_streams[stream_index as usize].mint != pool.staking_mint {
+            return Err(ErrorCode::CompoundRequiresStakingMint.into());
+        }
+
+        //compounded stake would have no receipts backing it
+        if pool.issues_receipts() {
+            return Err(ErrorCode::ReceiptsUnsupported.into());
+        }
+
+        let current_time = current_timestamp()?;
+        //without a fresh lock compounded rewards become withdrawable, so respect the claim lock
+        if !pool.compound_resets_lock && pool.claim_unlock_time(&ctx.accounts.user) > current_time {
+            return Err(ErrorCode::CannotStakeOrClaimBeforeMaturity.into());
+        }
+
+        let user_opt = Some(&mut ctx.accounts.user);
+        update_rewards(
+            pool,
+            user_opt,
+        )?;
+
+        let user = &mut ctx.accounts.user;
+        let amount = std::cmp::min(
+            user.reward_per_token_pending[stream_index as usize],
+            ctx.accounts.reward_vault.amount,
+        );
+        if amount == 0 {
+            return Err(ErrorCode::AmountMustBeGreaterThanZero.into());
+        }
+
+        let unlock_time = if pool.compound_resets_lock {
+            current_time.checked_add(pool.lock_period).ok_or(ErrorCode::MathOverflow)?
+        } else {
+            current_time
+        };
+        let weight = pool.lock_weight(amount, unlock_time.checked_sub(current_time).ok_or(ErrorCode::MathUnderflow)?)?;
+        user.add_position(amount, weight, current_time, unlock_time)?;
+        user.reward_per_token_pending[stream_index as usize] = user.reward_per_token_pending[stream_index as usize]
+            .checked_sub(amount)
+            .ok_or(ErrorCode::MathUnderflow)?;
+        let stream = &mut pool.reward_streams[stream_index as usize];
+        stream.reward_liability = stream.reward_liability.checked_sub(amount).ok_or(ErrorCode::MathUnderflow)?;
+        user.balance_staked = user.balance_staked.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        user.balance_weighted = user.balance_weighted.checked_add(weight).ok_or(ErrorCode::MathOverflow)?;
+        pool.total_staked = pool.total_staked.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        pool.total_weighted_stake = pool.total_weighted_stake.checked_add(weight).ok_or(ErrorCode::MathOverflow)?;
+        if pool.reward_lock_policy == RewardLockPolicy::Independent {
+            user.reward_unlock_time = current_time.checked_add(pool.reward_lock_period).ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        // Transfer rewards from the reward vault into the stake vault.
+        {
+            let seeds = &[
+                ctx.accounts.pool.to_account_info().key.as_ref(),
+                &[ctx.accounts.pool.nonce],
+            ];
+            let pool_signer = &[&seeds[..]];
+
+            let cpi_ctx = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.reward_vault.to_account_info(),
+                    to: ctx.accounts.staking_vault.to_account_info(),
+                    authority: ctx.accounts.pool_signer.to_account_info(),
+                },
+                pool_signer,
+            );
+            token::transfer(cpi_ctx, amount)?;
+        }
+
+        emit!(Compounded {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            owner: ctx.accounts.owner.key(),
+            stream_index,
+            amount,
+            weight,
+            unlock_time,
+            balance_staked: ctx.accounts.user.balance_staked,
+            total_staked: ctx.accounts.pool.total_staked,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    pub fn close_user(ctx: Context<CloseUser>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.user_stake_count = pool.user_stake_count.checked_sub(1).ok_or(ErrorCode::MathUnderflow)?;
+
+        emit!(UserClosed {
+            pool: pool.key(),
+            user: ctx.accounts.user.key(),
+            owner: ctx.accounts.owner.key(),
+        });
+        Ok(())
+    }
+
+    pub fn close_vesting(ctx: Context<CloseVesting>) -> Result<()> {
+        emit!(VestingClosed {
+            pool: ctx.accounts.pool.key(),
+            vesting: ctx.accounts.vesting.key(),
+            owner: ctx.accounts.owner.key(),
+        });
+        Ok(())
+    }
+
+    /// Reward vaults of streams other than the first are closed from remaining
+    /// accounts, passed as (reward vault, reward refundee) pairs in stream order.
+    pub fn close_pool<'info>(ctx: Context<'_, '_, '_, 'info, ClosePool<'info>>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let current_time = current_timestamp()?;
+        if pool.reward_duration_end() >= current_time {
+            return Err(ErrorCode::RewardPeriodActive.into());
+        }
+        
+        let signer_seeds = &[pool.to_account_info().key.as_ref(), &[ctx.accounts.pool.nonce]];
+        
+        //instead of closing these vaults, we could technically just 
+        //set_authority on them. it's not very ata clean, but it'd work
+        //if size of tx is an issue, thats an approach
+
+        //close staking vault
+        let ix = spl_token::instruction::transfer(
+            &spl_token::ID,
+            ctx.accounts.staking_vault.to_account_info().key,
+            ctx.accounts.staking_refundee.to_account_info().key,
+            ctx.accounts.pool_signer.key,
+            &[ctx.accounts.pool_signer.key],
+            ctx.accounts.staking_vault.amount,
+        )?;
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.staking_vault.to_account_info(),
+                ctx.accounts.staking_refundee.to_account_info(),
+                ctx.accounts.pool_signer.to_account_info(),
+            ],
+            &[signer_seeds],
+        )?;
+        let ix = spl_token::instruction::close_account(
+            &spl_token::ID,
+            ctx.accounts.staking_vault.to_account_info().key,
+            ctx.accounts.refundee.key,
+            ctx.accounts.pool_signer.key,
+            &[ctx.accounts.pool_signer.key],
+        )?;
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.staking_vault.to_account_info(),
+                ctx.accounts.refundee.to_account_info(),
+                ctx.accounts.pool_signer.to_account_info(),
+            ],
+            &[signer_seeds],
+        )?;
+        
+        //close token a vault
+        let ix = spl_token::instruction::transfer(
+            &spl_token::ID,
+            ctx.accounts.reward_vault.to_account_info().key,
+            ctx.accounts.reward_refundee.to_account_info().key,
+            ctx.accounts.pool_signer.key,
+            &[ctx.accounts.pool_signer.key],
+            ctx.accounts.reward_vault.amount,
+        )?;
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.reward_vault.to_account_info(),
+                ctx.accounts.reward_refundee.to_account_info(),
+                ctx.accounts.pool_signer.to_account_info(),
+            ],
+            &[signer_seeds],
+        )?;
+        let ix = spl_token::instruction::close_account(
+            &spl_token::ID,
+            ctx.accounts.reward_vault.to_account_info().key,
+            ctx.accounts.refundee.key,
+            ctx.accounts.pool_signer.key,
+            &[ctx.accounts.pool_signer.key],
+        )?;
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.reward_vault.to_account_info(),
+                ctx.accounts.refundee.to_account_info(),
+                ctx.accounts.pool_signer.to_account_info(),
+            ],
+            &[signer_seeds],
+        )?;
+
+        //close any additional reward vaults
+        let mut remaining = ctx.remaining_accounts.iter();
+        for stream in pool.reward_streams.iter().skip(1) {
+            if !stream.is_active() {
+                continue;
+            }
+
+            let (vault_info, vault_refundee) = match (remaining.next(), remaining.next()) {
+                (Some(vault_info), Some(vault_refundee)) => (vault_info, vault_refundee),
+                _ => return Err(ErrorCode::MissingRewardStreamAccounts.into()),
+            };
+            if *vault_info.key != stream.vault {
+                return Err(ErrorCode::InvalidRewardStream.into());
+            }
+            let reward_vault: Account<TokenAccount> = Account::try_from(vault_info)?;
+
+            let ix = spl_token::instruction::transfer(
+                &spl_token::ID,
+                vault_info.key,
+                vault_refundee.key,
+                ctx.accounts.pool_signer.key,
+                &[ctx.accounts.pool_signer.key],
+                reward_vault.amount,
+            )?;
+            solana_program::program::invoke_signed(
+                &ix,
+                &[
+                    ctx.accounts.token_program.to_account_info(),
+                    vault_info.clone(),
+                    vault_refundee.clone(),
+                    ctx.accounts.pool_signer.to_account_info(),
+                ],
+                &[signer_seeds],
+            )?;
+            let ix = spl_token::instruction::close_account(
+                &spl_token::ID,
+                vault_info.key,
+                ctx.accounts.refundee.key,
+                ctx.accounts.pool_signer.key,
+                &[ctx.accounts.pool_signer.key],
+            )?;
+            solana_program::program::invoke_signed(
+                &ix,
+                &[
+                    ctx.accounts.token_program.to_account_info(),
+                    vault_info.clone(),
+                    ctx.accounts.refundee.to_account_info(),
+                    ctx.accounts.pool_signer.to_account_info(),
+                ],
+                &[signer_seeds],
+            )?;
+        }
+
+        emit!(PoolClosed {
+            pool: ctx.accounts.pool.key(),
+            authority: ctx.accounts.authority.key(),
+            refundee: ctx.accounts.refundee.key(),
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct ViewPending<'info> {
+    pool: Box<Account<'info, Pool>>,
+    #[account(
+        has_one = pool,
+    )]
+    user: Box<Account<'info, User>>,
+}
+
+#[derive(Accounts)]
+#[instruction(pool_nonce: u8)]
+pub struct InitializePool<'info> {
+    authority: UncheckedAccount<'info>,
+
+    staking_mint: Box<Account<'info, Mint>>,
+    #[account(
+        constraint = staking_vault.mint == staking_mint.key(),
+        constraint = staking_vault.owner == pool_signer.key(),
+        //strangely, spl maintains this on owner reassignment for non-native accounts
+        //we don't want to be given an account that someone else could close when empty
+        //because in our "pool close" operation we want to assert it is still open
+        constraint = staking_vault.close_authority == COption::None,
+    )]
+    staking_vault: Box<Account<'info, TokenAccount>>,
+
+    reward_mint: Box<Account<'info, Mint>>,
+    #[account(
+        constraint = reward_vault.mint == reward_mint.key(),
+        constraint = reward_vault.owner == pool_signer.key(),
+        constraint = reward_vault.close_authority == COption::None,
+        //staking and reward tokens are accounted apart, sweeping must not reach rewards
+        constraint = reward_vault.key() != staking_vault.key(),
+    )]
+    reward_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        constraint = treasury.mint == staking_mint.key(),
+    )]
+    treasury: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool_nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+
+    #[account(
+        zero,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(nonce: u8)]
+pub struct CreateUser<'info> {
+    // Stake instance.
+    #[account(
+        mut,
+        constraint = !pool.is_paused(PAUSE_NEW_USERS),
+    )]
+    pool: Box<Account<'info, Pool>>,
+    // Member.
+    #[account(
+        init,
+        payer = owner,
+        seeds = [
+            owner.key.as_ref(), 
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = nonce,
+    )]
+    user: Box<Account<'info, User>>,
+    owner: Signer<'info>,
+    // Misc.
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(nonce: u8)]
+pub struct CreateVesting<'info> {
+    pool: Box<Account<'info, Pool>>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [
+            owner.key.as_ref(), 
+            pool.to_account_info().key.as_ref(),
+            b"vesting".as_ref(),
+        ],
+        bump = nonce,
+    )]
+    vesting: Box<Account<'info, Vesting>>,
+    owner: Signer<'info>,
+    // Misc.
+    system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Pause<'info> {
+    #[account(
+        mut, 
+        constraint = pauser.key() == pool.authority || pauser.key() == pool.guardian,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    pauser: Signer<'info>,
+
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Unpause<'info> {
+    #[account(
+        mut, 
+        has_one = authority,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    authority: Signer<'info>,
+
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetGuardian<'info> {
+    #[account(
+        mut, 
+        has_one = authority,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetEmergency<'info> {
+    #[account(
+        mut, 
+        has_one = authority,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EnableReceipts<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        has_one = staking_mint,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    authority: Signer<'info>,
+    staking_mint: Box<Account<'info, Mint>>,
+    #[account(
+        constraint = receipt_mint.mint_authority == COption::Some(pool_signer.key()),
+        constraint = receipt_mint.freeze_authority == COption::None,
+        constraint = receipt_mint.supply == 0,
+        constraint = receipt_mint.decimals == staking_mint.decimals,
+    )]
+    receipt_mint: Box<Account<'info, Mint>>,
+
+    // Program signers.
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SyncReceipt<'info> {
+    #[account(
+        mut,
+        has_one = receipt_mint,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    receipt_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        has_one = pool,
+    )]
+    user: Box<Account<'info, User>>,
+    // The holder's associated token account, which may have been closed.
+    #[account(
+        constraint = receipt_account.key() == pool.receipt_account(&user.owner),
+    )]
+    receipt_account: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Stake<'info> {
+    // Global accounts for the staking instance.
+    #[account(
+        mut, 
+        has_one = staking_vault,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    #[account(
+        mut,
+        constraint = staking_vault.owner == *pool_signer.key,
+    )]
+    staking_vault: Box<Account<'info, TokenAccount>>,
+
+    // User.
+    #[account(
+        mut, 
+        has_one = owner, 
+        has_one = pool,
+        seeds = [
+            owner.key.as_ref(), 
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = user.nonce,
+    )]
+    user: Box<Account<'info, User>>,
+    owner: Signer<'info>,
+    #[account(mut)]
+    stake_from_account: Box<Account<'info, TokenAccount>>,
+
+    // Program signers.
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+
+    // Misc.
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct EarlyUnstake<'info> {
+    // Global accounts for the staking instance.
+    #[account(
+        mut, 
+        has_one = staking_vault,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    #[account(
+        mut,
+        constraint = staking_vault.owner == *pool_signer.key,
+    )]
+    staking_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = penalty_vault.key() == pool.penalty_vault(),
+    )]
+    penalty_vault: Box<Account<'info, TokenAccount>>,
+
+    // User.
+    #[account(
+        mut, 
+        has_one = owner, 
+        has_one = pool,
+        seeds = [
+            owner.key.as_ref(), 
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = user.nonce,
+    )]
+    user: Box<Account<'info, User>>,
+    owner: Signer<'info>,
+    #[account(mut)]
+    stake_from_account: Box<Account<'info, TokenAccount>>,
+
+    // Program signers.
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+
+    // Misc.
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SweepExcessStake<'info> {
+    #[account(
+        has_one = authority,
+        has_one = staking_vault,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    authority: Signer<'info>,
+    #[account(mut)]
+    staking_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    destination: Box<Account<'info, TokenAccount>>,
+
+    // Program signers.
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+
+    // Misc.
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(stream_index: u8)]
+pub struct RecoverUndistributed<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    authority: Signer<'info>,
+    #[account(
+        mut,
+        constraint = pool.is_reward_vault(stream_index, &reward_vault.key()),
+    )]
+    reward_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    destination: Box<Account<'info, TokenAccount>>,
+
+    // Program signers.
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+
+    // Misc.
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePoolConfig<'info> {
+    #[account(
+        mut, 
+        has_one = authority,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    #[account(
+        mut, 
+        has_one = authority,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(
+        mut, 
+        has_one = pending_authority,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    pending_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FunderChange<'info> {
+    // Global accounts for the staking instance.
+    #[account(
+        mut, 
+        has_one = authority,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddRewardStream<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    authority: Signer<'info>,
+
+    reward_mint: Box<Account<'info, Mint>>,
+    #[account(
+        constraint = reward_vault.mint == reward_mint.key(),
+        constraint = reward_vault.owner == pool_signer.key(),
+        constraint = reward_vault.close_authority == COption::None,
+        constraint = reward_vault.key() != pool.staking_vault,
+    )]
+    reward_vault: Box<Account<'info, TokenAccount>>,
+
+    // Program signers.
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(stream_index: u8)]
+pub struct Fund<'info> {
+    // Global accounts for the staking instance.
+    #[account(
+        mut, 
+        has_one = staking_vault,
+        constraint = !pool.is_paused(PAUSE_FUNDING),
+    )]
+    pool: Box<Account<'info, Pool>>,
+    #[account(mut)]
+    staking_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = pool.is_reward_vault(stream_index, &reward_vault.key()),
+    )]
+    reward_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        //require signed funder auth - otherwise constant micro fund could hold funds hostage
+        constraint = funder.key() == pool.authority || pool.funders.iter().any(|x| *x == funder.key()),
+    )]
+    funder: Signer<'info>,
+    #[account(mut)]
+    from: Box<Account<'info, TokenAccount>>,
+
+    // Program signers.
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+
+    // Misc.
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(stream_index: u8)]
+pub struct ClaimReward<'info> {
+    // Global accounts for the staking instance.
+    #[account(
+        mut, 
+        has_one = staking_vault,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    #[account(mut)]
+    staking_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = pool.is_reward_vault(stream_index, &reward_vault.key()),
+    )]
+    reward_vault: Box<Account<'info, TokenAccount>>,
+
+    // User.
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = pool,
+        seeds = [
+            owner.to_account_info().key.as_ref(),
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = user.nonce,
+    )]
+    user: Box<Account<'info, User>>,
+    owner: Signer<'info>,
+    #[account(mut)]
+    reward_account: Box<Account<'info, TokenAccount>>,
+
+    // Program signers.
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+
+    // Misc.
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimAll<'info> {
+    // Global accounts for the staking instance.
+    #[account(mut)]
+    pool: Box<Account<'info, Pool>>,
+
+    // User.
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = pool,
+        seeds = [
+            owner.to_account_info().key.as_ref(),
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = user.nonce,
+    )]
+    user: Box<Account<'info, User>>,
+    owner: Signer<'info>,
+
+    // Program signers.
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+
+    // Misc.
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(stream_index: u8)]
+pub struct ClaimVesting<'info> {
+    // Global accounts for the staking instance.
+    #[account(
+        mut,
+        constraint = pool.is_active_stream(stream_index),
+    )]
+    pool: Box<Account<'info, Pool>>,
+
+    // User.
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = pool,
+        seeds = [
+            owner.to_account_info().key.as_ref(),
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = user.nonce,
+    )]
+    user: Box<Account<'info, User>>,
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = pool,
+        seeds = [
+            owner.to_account_info().key.as_ref(),
+            pool.to_account_info().key.as_ref(),
+            b"vesting".as_ref(),
+        ],
+        bump = vesting.nonce,
+    )]
+    vesting: Box<Account<'info, Vesting>>,
+    owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(stream_index: u8)]
+pub struct WithdrawVested<'info> {
+    // Global accounts for the staking instance.
+    #[account(mut)]
+    pool: Box<Account<'info, Pool>>,
+    #[account(
+        mut,
+        constraint = pool.is_reward_vault(stream_index, &reward_vault.key()),
+    )]
+    reward_vault: Box<Account<'info, TokenAccount>>,
+
+    // User.
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = pool,
+        seeds = [
+            owner.to_account_info().key.as_ref(),
+            pool.to_account_info().key.as_ref(),
+            b"vesting".as_ref(),
+        ],
+        bump = vesting.nonce,
+    )]
+    vesting: Box<Account<'info, Vesting>>,
+    owner: Signer<'info>,
+    #[account(mut)]
+    reward_account: Box<Account<'info, TokenAccount>>,
+
+    // Program signers.
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+
+    // Misc.
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(stream_index: u8)]
+pub struct Compound<'info> {
+    // Global accounts for the staking instance.
+    #[account(
+        mut, 
+        has_one = staking_vault,
+    )]
+    pool: Box<Account<'info, Pool>>,
+    #[account(mut)]
+    staking_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = pool.is_reward_vault(stream_index, &reward_vault.key()),
+    )]
+    reward_vault: Box<Account<'info, TokenAccount>>,
+
+    // User.
+    #[account(
+        mut,
+        has_one = owner,
+        has_one = pool,
+        seeds = [
+            owner.to_account_info().key.as_ref(),
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = user.nonce,
+    )]
+    user: Box<Account<'info, User>>,
+    owner: Signer<'info>,
+
+    // Program signers.
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+
+    // Misc.
+    token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct CloseUser<'info> {
+    #[account(
+        mut, 
+    )]
+    pool: Box<Account<'info, Pool>>,
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner,
+        has_one = pool,
+        seeds = [
+            owner.to_account_info().key.as_ref(),
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = user.nonce,
+        constraint = user.balance_staked == 0,
+        constraint = user.reward_per_token_pending.iter().all(|x| *x == 0),
+    )]
+    user: Account<'info, User>,
+    #[account(mut)]
+    owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseVesting<'info> {
+    pool: Box<Account<'info, Pool>>,
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner,
+        has_one = pool,
+        seeds = [
+            owner.to_account_info().key.as_ref(),
+            pool.to_account_info().key.as_ref(),
+            b"vesting".as_ref(),
+        ],
+        bump = vesting.nonce,
+        constraint = vesting.entries.iter().all(|x| x.withdrawn == x.amount),
+    )]
+    vesting: Account<'info, Vesting>,
+    #[account(mut)]
+    owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePool<'info> {
+    #[account(mut)]
+    refundee: UncheckedAccount<'info>,
+    #[account(mut)]
+    staking_refundee: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    reward_refundee: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        close = refundee,
+        has_one = authority,
+        has_one = staking_vault,
+        constraint = pool.is_reward_vault(0, &reward_vault.key()),
+        constraint = pool.is_paused(PAUSE_DEPOSITS | PAUSE_FUNDING | PAUSE_NEW_USERS),
+        constraint = pool.reward_duration_end() > 0,
+        constraint = pool.user_stake_count == 0,
+        constraint = pool.reward_streams.iter().all(|x| x.vesting_outstanding == 0),
+    )]
+    pool: Account<'info, Pool>,
+    authority: Signer<'info>,
+    #[account(mut,
+        constraint = staking_vault.amount == 0,
+    )]
+    staking_vault: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    reward_vault: Box<Account<'info, TokenAccount>>,
+    #[account(
+        seeds = [
+            pool.to_account_info().key.as_ref()
+        ],
+        bump = pool.nonce,
+    )]
+    pool_signer: UncheckedAccount<'info>,
+    token_program: Program<'info, Token>,
+}
+
+#[account]
+#[derive(Default)]
+pub struct Pool {
+    /// Priviledged account.
+    pub authority: Pubkey,
+    /// Account proposed to take over as authority, default when none.
+    pub pending_authority: Pubkey,
+    /// Nonce to derive the program-derived address owning the vaults.
+    pub nonce: u8,
+    /// Operations currently paused, a set of `PAUSE_*` flags.
+    pub pause_flags: u8,
+    /// Account allowed to pause but never unpause, default when none.
+    pub guardian: Pubkey,
+    /// Whether users may withdraw their stake through `emergency_withdraw`.
+    pub emergency: bool,
+    /// Mint of the token that can be staked.
+    pub staking_mint: Pubkey,
+    /// Vault to store staked tokens.
+    pub staking_vault: Pubkey,
+    /// The period which rewards are linearly distributed.
+    pub reward_duration: u64,
+    /// Period to lock staked token and rewards
+    pub lock_period: u64,
+    /// Reward token streams, the first is set at initialization.
+    pub reward_streams: [RewardStream; 4],
+    /// Users staked
+    pub user_stake_count: u32,
+    /// Tokens staked by users, excluding anything sent directly to the vault.
+    pub total_staked: u64,
+    /// Staked tokens weighted by lock duration, rewards are split over this.
+    pub total_weighted_stake: u64,
+    /// authorized funders
+    /// [] because short size, fixed account size, and ease of use on 
+    /// client due to auto generated account size property
+    pub funders: [Pubkey; 5],
+    /// Longest lock a deposit may choose, `lock_period` being the shortest.
+    pub max_lock_period: u64,
+    /// Weight multiplier in basis points for a deposit locked for `max_lock_period`.
+    pub max_lock_multiplier_bps: u64,
+    /// How the early unstake penalty is applied.
+    pub penalty_curve: PenaltyCurve,
+    /// Early unstake penalty in basis points of the locked amount.
+    pub early_unstake_penalty_bps: u16,
+    /// Where early unstake penalties are sent.
+    pub penalty_destination: PenaltyDestination,
+    /// Token account receiving penalties when sent to the treasury.
+    pub treasury: Pubkey,
+    /// When accrued rewards can be claimed.
+    pub reward_lock_policy: RewardLockPolicy,
+    /// Period rewards are locked for under the independent policy.
+    pub reward_lock_period: u64,
+    /// When the independent policy last took effect, rewards of users staked
+    /// before it unlock `reward_lock_period` after it.
+    pub reward_lock_start: u64,
+    /// Whether compounded rewards are locked for a new lock period.
+    pub compound_resets_lock: bool,
+    /// Period claimed rewards vest over, zero pays rewards out immediately.
+    pub vesting_period: u64,
+    /// Period after a claim before any of it vests.
+    pub vesting_cliff: u64,
+    /// Mint of the receipt tokens issued for stake, default when the pool issues none.
+    pub receipt_mint: Pubkey,
+}
+
+impl Pool {
+    /// Whether every operation in `flags` is paused.
+    pub fn is_paused(&self, flags: u8) -> bool {
+        self.pause_flags & flags == flags
+    }
+
+    /// Whether any operation in `flags` is paused.
+    pub fn is_any_paused(&self, flags: u8) -> bool {
+        self.pause_flags & flags != 0
+    }
+
+    pub fn issues_receipts(&self) -> bool {
+        self.receipt_mint != Pubkey::default()
+    }
+
+    /// The receipt account of an owner, their associated token account for the receipt mint.
+    pub fn receipt_account(&self, owner: &Pubkey) -> Pubkey {
+        get_associated_token_address(owner, &self.receipt_mint)
+    }
+
+    pub fn config(&self) -> PoolConfig {
+        PoolConfig {
+            max_lock_period: self.max_lock_period,
+            max_lock_multiplier_bps: self.max_lock_multiplier_bps,
+            penalty_curve: self.penalty_curve,
+            early_unstake_penalty_bps: self.early_unstake_penalty_bps,
+            penalty_destination: self.penalty_destination,
+            reward_lock_policy: self.reward_lock_policy,
+            reward_lock_period: self.reward_lock_period,
+            compound_resets_lock: self.compound_resets_lock,
+            vesting_period: self.vesting_period,
+            vesting_cliff: self.vesting_cliff,
+        }
+    }
+
+    pub fn apply_config(&mut self, config: &PoolConfig) {
+        self.max_lock_period = config.max_lock_period;
+        self.max_lock_multiplier_bps = config.max_lock_multiplier_bps;
+        self.penalty_curve = config.penalty_curve;
+        self.early_unstake_penalty_bps = config.early_unstake_penalty_bps;
+        self.penalty_destination = config.penalty_destination;
+        self.reward_lock_policy = config.reward_lock_policy;
+        self.reward_lock_period = config.reward_lock_period;
+        self.compound_resets_lock = config.compound_resets_lock;
+        self.vesting_period = config.vesting_period;
+        self.vesting_cliff = config.vesting_cliff;
+    }
+
+    /// The timestamp at which the last running reward period ends.
+    pub fn reward_duration_end(&self) -> u64 {
+        self.reward_streams
+            .iter()
+            .map(|x| x.reward_duration_end)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Reward weight of `amount` locked for `lock_duration`. The multiplier grows
+    /// linearly from 1x at `lock_period` to `max_lock_multiplier_bps` at `max_lock_period`.
+    pub fn lock_weight(&self, amount: u64, lock_duration: u64) -> Result<u64> {
+        let lock_range = self.max_lock_period.saturating_sub(self.lock_period);
+        if lock_range == 0 {
+            return Ok(amount);
+        }
+
+        let extra_bps = (self.max_lock_multiplier_bps.checked_sub(BPS_DENOMINATOR).ok_or(ErrorCode::MathUnderflow)? as u128)
+            .checked_mul(lock_duration.saturating_sub(self.lock_period) as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            / lock_range as u128;
+
+        let weight = (amount as u128)
+            .checked_mul((BPS_DENOMINATOR as u128).checked_add(extra_bps).ok_or(ErrorCode::MathOverflow)?)
+            .ok_or(ErrorCode::MathOverflow)?
+            / BPS_DENOMINATOR as u128;
+        u64::try_from(weight).map_err(|_| ErrorCode::ConversionFailed.into())
+    }
+
+    /// The time from which the user may claim rewards under the pool's policy.
+    pub fn claim_unlock_time(&self, user: &User) -> u64 {
+        match self.reward_lock_policy {
+            RewardLockPolicy::WithPrincipal => user.latest_unlock_time(),
+            RewardLockPolicy::Anytime => 0,
+            RewardLockPolicy::Independent => std::cmp::max(
+                user.reward_unlock_time,
+                self.reward_lock_start.saturating_add(self.reward_lock_period),
+            ),
+        }
+    }
+
+    /// The token account early unstake penalties are transferred to.
+    pub fn penalty_vault(&self) -> Pubkey {
+        match self.penalty_destination {
+            PenaltyDestination::Stakers => self.reward_streams[0].vault,
+            PenaltyDestination::Treasury => self.treasury,
+        }
+    }
+
+    /// Penalty owed for withdrawing `amount` of a position before it unlocks.
+    pub fn early_unstake_penalty(&self, amount: u64, position: &StakePosition, current_time: u64) -> Result<u64> {
+        if position.unlock_time <= current_time {
+            return Ok(0);
+        }
+
+        let flat_penalty = (amount as u128)
+            .checked_mul(self.early_unstake_penalty_bps as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            / BPS_DENOMINATOR as u128;
+
+        let penalty = match self.penalty_curve {
+            PenaltyCurve::Disabled => 0,
+            PenaltyCurve::Flat => flat_penalty,
+            PenaltyCurve::LinearDecay => {
+                let lock_duration = position.unlock_time.saturating_sub(position.start_time);
+                if lock_duration == 0 {
+                    0
+                } else {
+                    flat_penalty
+                        .checked_mul(position.unlock_time.checked_sub(current_time).ok_or(ErrorCode::MathUnderflow)? as u128)
+                        .ok_or(ErrorCode::MathOverflow)?
+                        / lock_duration as u128
+                }
+            }
+        };
+
+        u64::try_from(penalty).map_err(|_| ErrorCode::ConversionFailed.into())
+    }
+
+    pub fn is_active_stream(&self, stream_index: u8) -> bool {
+        match self.reward_streams.get(stream_index as usize) {
+            Some(stream) => stream.is_active(),
+            None => false,
+        }
+    }
+
+    pub fn is_reward_vault(&self, stream_index: u8, vault: &Pubkey) -> bool {
+        self.is_active_stream(stream_index)
+            && self.reward_streams[stream_index as usize].vault == *vault
+    }
+}
+
+/// Optional pool behaviour, set at initialization and by `update_pool_config`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct PoolConfig {
+    pub max_lock_period: u64,
+    pub max_lock_multiplier_bps: u64,
+    pub penalty_curve: PenaltyCurve,
+    pub early_unstake_penalty_bps: u16,
+    pub penalty_destination: PenaltyDestination,
+    pub reward_lock_policy: RewardLockPolicy,
+    pub reward_lock_period: u64,
+    pub compound_resets_lock: bool,
+    pub vesting_period: u64,
+    pub vesting_cliff: u64,
+}
+
+impl PoolConfig {
+    pub fn validate(&self, lock_period: u64, staking_mint: &Pubkey, first_reward_mint: &Pubkey) -> Result<()> {
+        if self.max_lock_period < lock_period || self.max_lock_multiplier_bps < BPS_DENOMINATOR {
+            return Err(ErrorCode::InvalidLockConfig.into());
+        }
+
+        if self.vesting_cliff > self.vesting_period {
+            return Err(ErrorCode::InvalidVestingConfig.into());
+        }
+
+        if self.early_unstake_penalty_bps as u64 > BPS_DENOMINATOR {
+            return Err(ErrorCode::InvalidPenaltyConfig.into());
+        }
+        //redistributed penalties are paid out of the first reward stream
+        if self.penalty_destination == PenaltyDestination::Stakers && first_reward_mint != staking_mint {
+            return Err(ErrorCode::InvalidPenaltyConfig.into());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Default)]
+pub enum PenaltyCurve {
+    /// Early unstaking is not allowed.
+    #[default]
+    Disabled,
+    /// The full penalty applies until the position unlocks.
+    Flat,
+    /// The penalty decays linearly to zero at the position's unlock time.
+    LinearDecay,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Default)]
+pub enum PenaltyDestination {
+    /// Redistributed to remaining stakers through the first reward stream.
+    #[default]
+    Stakers,
+    /// Sent to the pool treasury.
+    Treasury,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Default)]
+pub enum RewardLockPolicy {
+    /// Rewards unlock once all of the user's deposits unlock, so a top-up locks
+    /// rewards earned so far until it unlocks too.
+    #[default]
+    WithPrincipal,
+    /// Rewards can be claimed at any time.
+    Anytime,
+    /// Rewards unlock `reward_lock_period` after the first deposit or last claim,
+    /// and no earlier than that after the policy took effect.
+    Independent,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RewardStream {
+    /// Mint of the reward token.
+    pub mint: Pubkey,
+    /// Vault to store reward tokens.
+    pub vault: Pubkey,
+    /// The timestamp at which the current reward period starts emitting.
+    pub reward_start_time: u64,
+    /// The timestamp at which the current reward period ends.
+    pub reward_duration_end: u64,
+    /// The last time reward states were updated.
+    pub last_update_time: u64,
+    /// Rate of reward distribution per second, scaled by `PRECISION`.
+    pub reward_rate: u128,
+    /// Last calculated reward per pool token.
+    pub reward_per_token_stored: u128,
+    /// Claimed rewards held in the vault for vesting entries.
+    pub vesting_outstanding: u64,
+    /// Rewards held in the vault that no staker accrued, rolled into the next funding.
+    pub undistributed: u64,
+    /// Rewards accrued to stakers but not yet claimed.
+    pub reward_liability: u64,
+    /// Accrued rewards short of a whole token, scaled by `PRECISION`.
+    pub liability_remainder: u128,
+}
+
+impl RewardStream {
+    /// The accounting state shared with `reward_pool_math`.
+    pub fn state(&self) -> StreamState {
+        StreamState {
+            reward_start_time: self.reward_start_time,
+            reward_duration_end: self.reward_duration_end,
+            last_update_time: self.last_update_time,
+            reward_rate: self.reward_rate,
+            reward_per_token_stored: self.reward_per_token_stored,
+        }
+    }
+
+    pub fn set_state(&mut self, state: &StreamState) {
+        self.reward_start_time = state.reward_start_time;
+        self.reward_duration_end = state.reward_duration_end;
+        self.last_update_time = state.last_update_time;
+        self.reward_rate = state.reward_rate;
+        self.reward_per_token_stored = state.reward_per_token_stored;
+    }
+
+    /// The rewards held for stakers, shared with `reward_pool_math`.
+    pub fn liability(&self) -> StreamLiability {
+        StreamLiability {
+            reward_liability: self.reward_liability,
+            liability_remainder: self.liability_remainder,
+            undistributed: self.undistributed,
+        }
+    }
+
+    pub fn set_liability(&mut self, liability: &StreamLiability) {
+        self.reward_liability = liability.reward_liability;
+        self.liability_remainder = liability.liability_remainder;
+        self.undistributed = liability.undistributed;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.mint != Pubkey::default()
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct User {
+    /// Pool the this user belongs to.
+    pub pool: Pubkey,
+    /// The owner of this account.
+    pub owner: Pubkey,
+    /// The amount of each reward stream claimed.
+    pub reward_per_token_complete: [u128; 4],
+    /// The amount of each reward stream pending claim.
+    pub reward_per_token_pending: [u64; 4],
+    /// The amount staked.
+    pub balance_staked: u64,
+    /// The amount staked weighted by lock duration, used for reward accrual.
+    pub balance_weighted: u64,
+    /// Open deposits, oldest first.
+    pub positions: [StakePosition; 16],
+    /// Time rewards unlock under the independent reward lock policy.
+    pub reward_unlock_time: u64,
+    /// Signer nonce.
+    pub nonce: u8,
+}
+
+impl User {
+    /// Records a deposit made at `start_time`, merging it into the newest position when
+    /// both unlock together. With every position open, matured ones are merged to make room.
+    pub fn add_position(&mut self, amount: u64, weight: u64, start_time: u64, unlock_time: u64) -> Result<()> {
+        let mut open = self.positions.iter().filter(|x| x.amount > 0).count();
+        if open > 0
+            && self.positions[open - 1].unlock_time == unlock_time
+            && self.positions[open - 1].start_time == start_time
+        {
+            let last = &mut self.positions[open - 1];
+            last.amount = last.amount.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+            last.weight = last.weight.checked_add(weight).ok_or(ErrorCode::MathOverflow)?;
+            return Ok(());
+        }
+        if open == MAX_STAKE_POSITIONS {
+            open = self.merge_matured_positions(start_time)?;
+        }
+        if open == MAX_STAKE_POSITIONS {
+            return Err(ErrorCode::MaxStakePositions.into());
+        }
+        self.positions[open] = StakePosition {
+            amount,
+            weight,
+            start_time,
+            unlock_time,
+        };
+        Ok(())
+    }
+
+    /// The accounting state of a stream shared with `reward_pool_math`.
+    pub fn stream_state(&self, stream_index: usize) -> UserStreamState {
+        UserStreamState {
+            balance_weighted: self.balance_weighted,
+            reward_per_token_complete: self.reward_per_token_complete[stream_index],
+            reward_per_token_pending: self.reward_per_token_pending[stream_index],
+        }
+    }
+
+    pub fn set_stream_state(&mut self, stream_index: usize, state: &UserStreamState) {
+        self.reward_per_token_complete[stream_index] = state.reward_per_token_complete;
+        self.reward_per_token_pending[stream_index] = state.reward_per_token_pending;
+    }
+
+    /// The amount held in positions that have reached their unlock time.
+    pub fn matured_balance(&self, current_time: u64) -> Result<u64> {
+        let mut total: u64 = 0;
+        for position in self.positions.iter().filter(|x| x.amount > 0 && x.unlock_time <= current_time) {
+            total = total.checked_add(position.amount).ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(total)
+    }
+
+    /// The earliest time any open position unlocks, zero when nothing is staked.
+    pub fn earliest_unlock_time(&self) -> u64 {
+        self.positions
+            .iter()
+            .filter(|x| x.amount > 0)
+            .map(|x| x.unlock_time)
+            .min()
+            .unwrap_or(0)
+    }
 
-    pub fn close_user(ctx: Context<CloseUser>) -> Result<()> {
-        let pool = &mut ctx.accounts.pool;
-        pool.user_stake_count = pool.user_stake_count.checked_sub(1).unwrap();
-        Ok(())
+    /// The latest time any open position unlocks, zero when nothing is staked.
+    pub fn latest_unlock_time(&self) -> u64 {
+        self.positions
+            .iter()
+            .filter(|x| x.amount > 0)
+            .map(|x| x.unlock_time)
+            .max()
+            .unwrap_or(0)
     }
 
-    pub fn close_pool<'info>(ctx: Context<ClosePool>) -> Result<()> {
-        let pool = &ctx.accounts.pool;
-        
-        let signer_seeds = &[pool.to_account_info().key.as_ref(), &[ctx.accounts.pool.nonce]];
-        
-        //instead of closing these vaults, we could technically just 
-        //set_authority on them. it's not very ata clean, but it'd work
-        //if size of tx is an issue, thats an approach
+    /// Merges positions that have reached their unlock time into the oldest of them,
+    /// which stays matured, and returns the number of open positions left.
+    pub fn merge_matured_positions(&mut self, current_time: u64) -> Result<usize> {
+        let mut merged: Option<usize> = None;
+        for i in 0..MAX_STAKE_POSITIONS {
+            let position = self.positions[i];
+            if position.amount == 0 || position.unlock_time > current_time {
+                continue;
+            }
+            match merged {
+                None => merged = Some(i),
+                Some(first) => {
+                    let into = &mut self.positions[first];
+                    into.amount = into.amount.checked_add(position.amount).ok_or(ErrorCode::MathOverflow)?;
+                    into.weight = into.weight.checked_add(position.weight).ok_or(ErrorCode::MathOverflow)?;
+                    into.start_time = std::cmp::min(into.start_time, position.start_time);
+                    into.unlock_time = std::cmp::max(into.unlock_time, position.unlock_time);
+                    self.positions[i] = StakePosition::default();
+                }
+            }
+        }
+        self.compact_positions();
 
-        //close staking vault
-        let ix = spl_token::instruction::transfer(
-            &spl_token::ID,
-            ctx.accounts.staking_vault.to_account_info().key,
-            ctx.accounts.staking_refundee.to_account_info().key,
-            ctx.accounts.pool_signer.key,
-            &[ctx.accounts.pool_signer.key],
-            ctx.accounts.staking_vault.amount,
-        )?;
-        solana_program::program::invoke_signed(
-            &ix,
-            &[
-                ctx.accounts.token_program.to_account_info(),
-                ctx.accounts.staking_vault.to_account_info(),
-                ctx.accounts.staking_refundee.to_account_info(),
-                ctx.accounts.pool_signer.to_account_info(),
-            ],
-            &[signer_seeds],
-        )?;
-        let ix = spl_token::instruction::close_account(
-            &spl_token::ID,
-            ctx.accounts.staking_vault.to_account_info().key,
-            ctx.accounts.refundee.key,
-            ctx.accounts.pool_signer.key,
-            &[ctx.accounts.pool_signer.key],
-        )?;
-        solana_program::program::invoke_signed(
-            &ix,
-            &[
-                ctx.accounts.token_program.to_account_info(),
-                ctx.accounts.staking_vault.to_account_info(),
-                ctx.accounts.refundee.to_account_info(),
-                ctx.accounts.pool_signer.to_account_info(),
-            ],
-            &[signer_seeds],
-        )?;
-        
-        //close token a vault
-        let ix = spl_token::instruction::transfer(
-            &spl_token::ID,
-            ctx.accounts.reward_vault.to_account_info().key,
-            ctx.accounts.reward_refundee.to_account_info().key,
-            ctx.accounts.pool_signer.key,
-            &[ctx.accounts.pool_signer.key],
-            ctx.accounts.reward_vault.amount,
-        )?;
-        solana_program::program::invoke_signed(
-            &ix,
-            &[
-                ctx.accounts.token_program.to_account_info(),
-                ctx.accounts.reward_vault.to_account_info(),
-                ctx.accounts.reward_refundee.to_account_info(),
-                ctx.accounts.pool_signer.to_account_info(),
-            ],
-            &[signer_seeds],
-        )?;
-        let ix = spl_token::instruction::close_account(
-            &spl_token::ID,
-            ctx.accounts.reward_vault.to_account_info().key,
-            ctx.accounts.refundee.key,
-            ctx.accounts.pool_signer.key,
-            &[ctx.accounts.pool_signer.key],
-        )?;
-        solana_program::program::invoke_signed(
-            &ix,
-            &[
-                ctx.accounts.token_program.to_account_info(),
-                ctx.accounts.reward_vault.to_account_info(),
-                ctx.accounts.refundee.to_account_info(),
-                ctx.accounts.pool_signer.to_account_info(),
-            ],
-            &[signer_seeds],
-        )?;
+        Ok(self.positions.iter().filter(|x| x.amount > 0).count())
+    }
+
+    /// Removes `amount` from matured positions, oldest first, returning the weight removed.
+    /// Callers must check `matured_balance` covers the amount.
+    pub fn consume_matured_positions(&mut self, amount: u64, current_time: u64) -> Result<u64> {
+        let mut remaining = amount;
+        let mut weight: u64 = 0;
+        for position in self.positions.iter_mut() {
+            if remaining == 0 {
+                break;
+            }
+            if position.amount == 0 || position.unlock_time > current_time {
+                continue;
+            }
+            let taken = std::cmp::min(position.amount, remaining);
+            weight = weight.checked_add(position.take(taken)?).ok_or(ErrorCode::MathOverflow)?;
+            remaining = remaining.checked_sub(taken).ok_or(ErrorCode::MathUnderflow)?;
+        }
+        self.compact_positions();
+
+        Ok(weight)
+    }
+
+    /// Removes `amount` from open positions, matured ones first and then locked
+    /// ones oldest first. Returns the penalty owed on the locked part and the weight removed.
+    pub fn consume_positions_early(&mut self, amount: u64, current_time: u64, pool: &Pool) -> Result<(u64, u64)> {
+        let matured = std::cmp::min(self.matured_balance(current_time)?, amount);
+        let mut weight = self.consume_matured_positions(matured, current_time)?;
+
+        let mut remaining = amount.checked_sub(matured).ok_or(ErrorCode::MathUnderflow)?;
+        let mut penalty: u64 = 0;
+        for position in self.positions.iter_mut() {
+            if remaining == 0 {
+                break;
+            }
+            if position.amount == 0 {
+                continue;
+            }
+            let taken = std::cmp::min(position.amount, remaining);
+            penalty = penalty
+                .checked_add(pool.early_unstake_penalty(taken, position, current_time)?)
+                .ok_or(ErrorCode::MathOverflow)?;
+            weight = weight.checked_add(position.take(taken)?).ok_or(ErrorCode::MathOverflow)?;
+            remaining = remaining.checked_sub(taken).ok_or(ErrorCode::MathUnderflow)?;
+        }
+        self.compact_positions();
+
+        Ok((penalty, weight))
+    }
+
+    /// Sets the stake of a receipt holder, held in one matured position as receipt
+    /// pools have no locks.
+    pub fn set_receipt_balance(&mut self, balance: u64, current_time: u64) {
+        self.balance_staked = balance;
+        self.balance_weighted = balance;
+        self.positions = [StakePosition::default(); MAX_STAKE_POSITIONS];
+        if balance > 0 {
+            self.positions[0] = StakePosition {
+                amount: balance,
+                weight: balance,
+                start_time: current_time,
+                unlock_time: current_time,
+            };
+        }
+    }
+
+    /// Shifts open positions to the front, keeping their order.
+    pub fn compact_positions(&mut self) {
+        let mut open = 0;
+        for i in 0..MAX_STAKE_POSITIONS {
+            if self.positions[i].amount > 0 {
+                self.positions[open] = self.positions[i];
+                open += 1;
+            }
+        }
+        for position in self.positions.iter_mut().skip(open) {
+            *position = StakePosition::default();
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct StakePosition {
+    /// The amount deposited.
+    pub amount: u64,
+    /// The amount deposited scaled by the lock multiplier.
+    pub weight: u64,
+    /// The time of the deposit.
+    pub start_time: u64,
+    /// The time the deposit can be unstaked.
+    pub unlock_time: u64,
+}
+
+impl StakePosition {
+    /// Removes `amount` from the position, returning the proportional weight removed.
+    pub fn take(&mut self, amount: u64) -> Result<u64> {
+        let weight = if amount == self.amount {
+            self.weight
+        } else {
+            let weight = (self.weight as u128)
+                .checked_mul(amount as u128)
+                .ok_or(ErrorCode::MathOverflow)?
+                / self.amount as u128;
+            u64::try_from(weight).map_err(|_| ErrorCode::ConversionFailed)?
+        };
+        self.amount = self.amount.checked_sub(amount).ok_or(ErrorCode::MathUnderflow)?;
+        self.weight = self.weight.checked_sub(weight).ok_or(ErrorCode::MathUnderflow)?;
+        Ok(weight)
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct Vesting {
+    /// Pool the this vesting account belongs to.
+    pub pool: Pubkey,
+    /// The owner of this account.
+    pub owner: Pubkey,
+    /// Claimed rewards vesting linearly.
+    pub entries: [VestingEntry; 8],
+    /// Signer nonce.
+    pub nonce: u8,
+}
+
+impl Vesting {
+    /// Records a claim, merging it into an entry from the same stream and time.
+    pub fn add_entry(&mut self, entry: VestingEntry) -> Result<()> {
+        if let Some(existing) = self.entries.iter_mut().find(|x| {
+            x.amount > x.withdrawn && x.stream_index == entry.stream_index && x.start_time == entry.start_time
+        }) {
+            existing.amount = existing.amount.checked_add(entry.amount).ok_or(ErrorCode::MathOverflow)?;
+            return Ok(());
+        }
+        if let Some(free) = self.entries.iter_mut().find(|x| x.amount == x.withdrawn) {
+            *free = entry;
+            return Ok(());
+        }
+        Err(ErrorCode::MaxVestingEntries.into())
+    }
+
+    /// The vested but not yet withdrawn amount of a stream.
+    pub fn releasable(&self, stream_index: u8, current_time: u64) -> Result<u64> {
+        let mut total: u64 = 0;
+        for entry in self.entries.iter().filter(|x| x.stream_index == stream_index) {
+            total = total.checked_add(entry.releasable(current_time)?).ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(total)
+    }
 
+    /// Marks `amount` of a stream's vested rewards withdrawn, oldest entries first.
+    pub fn release(&mut self, stream_index: u8, amount: u64, current_time: u64) -> Result<()> {
+        let mut remaining = amount;
+        for entry in self.entries.iter_mut().filter(|x| x.stream_index == stream_index) {
+            if remaining == 0 {
+                break;
+            }
+            let taken = std::cmp::min(entry.releasable(current_time)?, remaining);
+            entry.withdrawn = entry.withdrawn.checked_add(taken).ok_or(ErrorCode::MathOverflow)?;
+            remaining = remaining.checked_sub(taken).ok_or(ErrorCode::MathUnderflow)?;
+        }
         Ok(())
     }
 }
 
-#[derive(Accounts)]
-#[instruction(pool_nonce: u8)]
-pub struct InitializePool<'info> {
-    authority: UncheckedAccount<'info>,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct VestingEntry {
+    /// Reward stream the claim was made from.
+    pub stream_index: u8,
+    /// The amount claimed.
+    pub amount: u64,
+    /// The amount withdrawn so far.
+    pub withdrawn: u64,
+    /// The time of the claim.
+    pub start_time: u64,
+    /// The time before which nothing vests.
+    pub cliff_time: u64,
+    /// The time the claim is fully vested.
+    pub end_time: u64,
+}
 
-    staking_mint: Box<Account<'info, Mint>>,
-    #[account(
-        constraint = staking_vault.mint == staking_mint.key(),
-        constraint = staking_vault.owner == pool_signer.key(),
-        //strangely, spl maintains this on owner reassignment for non-native accounts
-        //we don't want to be given an account that someone else could close when empty
-        //because in our "pool close" operation we want to assert it is still open
-        constraint = staking_vault.close_authority == COption::None,
-    )]
-    staking_vault: Box<Account<'info, TokenAccount>>,
+impl VestingEntry {
+    pub fn vested(&self, current_time: u64) -> Result<u64> {
+        if current_time < self.cliff_time {
+            return Ok(0);
+        }
+        if current_time >= self.end_time {
+            return Ok(self.amount);
+        }
+        let vested = (self.amount as u128)
+            .checked_mul(current_time.checked_sub(self.start_time).ok_or(ErrorCode::MathUnderflow)? as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            / self.end_time.checked_sub(self.start_time).ok_or(ErrorCode::MathUnderflow)? as u128;
+        u64::try_from(vested).map_err(|_| ErrorCode::ConversionFailed.into())
+    }
 
-    reward_mint: Box<Account<'info, Mint>>,
-    #[account(
-        constraint = reward_vault.mint == reward_mint.key(),
-        constraint = reward_vault.owner == pool_signer.key(),
-        constraint = reward_vault.close_authority == COption::None,
-    )]
-    reward_vault: Box<Account<'info, TokenAccount>>,
+    pub fn releasable(&self, current_time: u64) -> Result<u64> {
+        Ok(self.vested(current_time)?.saturating_sub(self.withdrawn))
+    }
+}
 
-    #[account(
-        seeds = [
-            pool.to_account_info().key.as_ref()
-        ],
-        bump = pool_nonce,
-    )]
-    pool_signer: UncheckedAccount<'info>,
+#[event]
+pub struct PoolInitialized {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub staking_mint: Pubkey,
+    pub reward_mint: Pubkey,
+    pub reward_duration: u64,
+    pub lock_period: u64,
+    pub config: PoolConfig,
+    pub timestamp: u64,
+}
 
-    #[account(
-        zero,
-    )]
-    pool: Box<Account<'info, Pool>>,
-    
-    token_program: Program<'info, Token>,
+#[event]
+pub struct PoolConfigUpdated {
+    pub pool: Pubkey,
+    pub reward_duration: u64,
+    pub lock_period: u64,
+    pub config: PoolConfig,
 }
 
-#[derive(Accounts)]
-#[instruction(nonce: u8)]
-pub struct CreateUser<'info> {
-    // Stake instance.
-    #[account(
-        mut,
-        constraint = !pool.paused,
-    )]
-    pool: Box<Account<'info, Pool>>,
-    // Member.
-    #[account(
-        init,
-        payer = owner,
-        seeds = [
-            owner.key.as_ref(), 
-            pool.to_account_info().key.as_ref()
-        ],
-        bump = nonce,
-    )]
-    user: Box<Account<'info, User>>,
-    owner: Signer<'info>,
-    // Misc.
-    system_program: Program<'info, System>,
+#[event]
+pub struct AuthorityProposed {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct Pause<'info> {
-    #[account(
-        mut, 
-        has_one = authority,
-        constraint = !pool.paused,
-        constraint = pool.reward_duration_end < clock::Clock::get().unwrap().unix_timestamp.try_into().unwrap(),
-        //constraint = pool.reward_duration_end > 0,
-    )]
-    pool: Box<Account<'info, Pool>>,
-    authority: Signer<'info>,
+#[event]
+pub struct AuthorityAccepted {
+    pub pool: Pubkey,
+    pub previous_authority: Pubkey,
+    pub authority: Pubkey,
+}
 
-    #[account(
-        seeds = [
-            pool.to_account_info().key.as_ref()
-        ],
-        bump = pool.nonce,
-    )]
-    pool_signer: UncheckedAccount<'info>,
-    token_program: Program<'info, Token>,
+#[event]
+pub struct AuthorityTransferCancelled {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct Unpause<'info> {
-    #[account(
-        mut, 
-        has_one = authority,
-        constraint = pool.paused,
-    )]
-    pool: Box<Account<'info, Pool>>,
-    authority: Signer<'info>,
+#[event]
+pub struct PoolPaused {
+    pub pool: Pubkey,
+    pub pauser: Pubkey,
+    /// Flags paused by this instruction.
+    pub flags: u8,
+    /// All flags paused afterwards.
+    pub pause_flags: u8,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct PoolUnpaused {
+    pub pool: Pubkey,
+    /// Flags resumed by this instruction.
+    pub flags: u8,
+    /// All flags paused afterwards.
+    pub pause_flags: u8,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct GuardianSet {
+    pub pool: Pubkey,
+    pub guardian: Pubkey,
+}
+
+#[event]
+pub struct EmergencySet {
+    pub pool: Pubkey,
+    pub enabled: bool,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct ReceiptsEnabled {
+    pub pool: Pubkey,
+    pub receipt_mint: Pubkey,
+}
+
+#[event]
+pub struct ReceiptSynced {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub owner: Pubkey,
+    pub receipt_balance: u64,
+    /// Less than the receipt balance while receipts came from a holder not yet synced.
+    pub balance_staked: u64,
+    pub total_weighted_stake: u64,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct FunderAuthorized {
+    pub pool: Pubkey,
+    pub funder: Pubkey,
+}
 
-    #[account(
-        seeds = [
-            pool.to_account_info().key.as_ref()
-        ],
-        bump = pool.nonce,
-    )]
-    pool_signer: UncheckedAccount<'info>,
-    token_program: Program<'info, Token>,
+#[event]
+pub struct FunderDeauthorized {
+    pub pool: Pubkey,
+    pub funder: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct Stake<'info> {
-    // Global accounts for the staking instance.
-    #[account(
-        mut, 
-        has_one = staking_vault,
-    )]
-    pool: Box<Account<'info, Pool>>,
-    #[account(
-        mut,
-        constraint = staking_vault.owner == *pool_signer.key,
-    )]
-    staking_vault: Box<Account<'info, TokenAccount>>,
+#[event]
+pub struct RewardStreamAdded {
+    pub pool: Pubkey,
+    pub stream_index: u8,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+}
 
-    // User.
-    #[account(
-        mut, 
-        has_one = owner, 
-        has_one = pool,
-        seeds = [
-            owner.key.as_ref(), 
-            pool.to_account_info().key.as_ref()
-        ],
-        bump = user.nonce,
-    )]
-    user: Box<Account<'info, User>>,
-    owner: Signer<'info>,
-    #[account(mut)]
-    stake_from_account: Box<Account<'info, TokenAccount>>,
+#[event]
+pub struct PoolFunded {
+    pub pool: Pubkey,
+    pub funder: Pubkey,
+    pub stream_index: u8,
+    pub amount: u64,
+    pub reward_rate: u128,
+    pub reward_per_token_stored: u128,
+    pub reward_start_time: u64,
+    pub reward_duration_end: u64,
+    pub timestamp: u64,
+}
 
-    // Program signers.
-    #[account(
-        seeds = [
-            pool.to_account_info().key.as_ref()
-        ],
-        bump = pool.nonce,
-    )]
-    pool_signer: UncheckedAccount<'info>,
+#[event]
+pub struct UndistributedRecovered {
+    pub pool: Pubkey,
+    pub stream_index: u8,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
 
-    // Misc.
-    token_program: Program<'info, Token>,
+#[event]
+pub struct ExcessStakeSwept {
+    pub pool: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
 }
 
-#[derive(Accounts)]
-pub struct FunderChange<'info> {
-    // Global accounts for the staking instance.
-    #[account(
-        mut, 
-        has_one = authority,
-    )]
-    pool: Box<Account<'info, Pool>>,
-    authority: Signer<'info>,
+#[event]
+pub struct UserCreated {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub owner: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct Fund<'info> {
-    // Global accounts for the staking instance.
-    #[account(
-        mut, 
-        has_one = staking_vault,
-        has_one = reward_vault,
-        constraint = !pool.paused,
-    )]
-    pool: Box<Account<'info, Pool>>,
-    #[account(mut)]
-    staking_vault: Box<Account<'info, TokenAccount>>,
-    #[account(mut)]
-    reward_vault: Box<Account<'info, TokenAccount>>,
-    #[account(
-        //require signed funder auth - otherwise constant micro fund could hold funds hostage
-        constraint = funder.key() == pool.authority || pool.funders.iter().any(|x| *x == funder.key()),
-    )]
-    funder: Signer<'info>,
-    #[account(mut)]
-    from: Box<Account<'info, TokenAccount>>,
+#[event]
+pub struct UserClosed {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub owner: Pubkey,
+}
 
-    // Program signers.
-    #[account(
-        seeds = [
-            pool.to_account_info().key.as_ref()
-        ],
-        bump = pool.nonce,
-    )]
-    pool_signer: UncheckedAccount<'info>,
+#[event]
+pub struct Staked {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub weight: u64,
+    pub unlock_time: u64,
+    pub balance_staked: u64,
+    pub total_staked: u64,
+    pub timestamp: u64,
+}
 
-    // Misc.
-    token_program: Program<'info, Token>,
+#[event]
+pub struct Unstaked {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub owner: Pubkey,
+    /// Amount withdrawn from the position, including any penalty.
+    pub amount: u64,
+    pub weight: u64,
+    pub penalty: u64,
+    pub balance_staked: u64,
+    pub total_staked: u64,
+    pub timestamp: u64,
 }
 
-#[derive(Accounts)]
-pub struct ClaimReward<'info> {
-    // Global accounts for the staking instance.
-    #[account(
-        mut, 
-        has_one = staking_vault,
-        has_one = reward_vault,
-    )]
-    pool: Box<Account<'info, Pool>>,
-    #[account(mut)]
-    staking_vault: Box<Account<'info, TokenAccount>>,
-    #[account(mut)]
-    reward_vault: Box<Account<'info, TokenAccount>>,
+#[event]
+pub struct EmergencyWithdrawn {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub timestamp: u64,
+}
 
-    // User.
-    #[account(
-        mut,
-        has_one = owner,
-        has_one = pool,
-        seeds = [
-            owner.to_account_info().key.as_ref(),
-            pool.to_account_info().key.as_ref()
-        ],
-        bump = user.nonce,
-    )]
-    user: Box<Account<'info, User>>,
-    owner: Signer<'info>,
-    #[account(mut)]
-    reward_account: Box<Account<'info, TokenAccount>>,
+#[event]
+pub struct Compounded {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub owner: Pubkey,
+    pub stream_index: u8,
+    pub amount: u64,
+    pub weight: u64,
+    pub unlock_time: u64,
+    pub balance_staked: u64,
+    pub total_staked: u64,
+    pub timestamp: u64,
+}
 
-    // Program signers.
-    #[account(
-        seeds = [
-            pool.to_account_info().key.as_ref()
-        ],
-        bump = pool.nonce,
-    )]
-    pool_signer: UncheckedAccount<'info>,
+#[event]
+pub struct RewardPaid {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub owner: Pubkey,
+    pub stream_index: u8,
+    pub amount: u64,
+    /// Reward left pending when the vault could not cover it.
+    pub pending: u64,
+    pub reward_per_token_stored: u128,
+    pub timestamp: u64,
+}
 
-    // Misc.
-    token_program: Program<'info, Token>,
+#[event]
+pub struct VestingCreated {
+    pub pool: Pubkey,
+    pub vesting: Pubkey,
+    pub owner: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct CloseUser<'info> {
-    #[account(
-        mut, 
-    )]
-    pool: Box<Account<'info, Pool>>,
-    #[account(
-        mut,
-        close = owner,
-        has_one = owner,
-        has_one = pool,
-        seeds = [
-            owner.to_account_info().key.as_ref(),
-            pool.to_account_info().key.as_ref()
-        ],
-        bump = user.nonce,
-        constraint = user.balance_staked == 0,
-        constraint = user.reward_per_token_pending == 0,
-    )]
-    user: Account<'info, User>,
-    owner: Signer<'info>,
+#[event]
+pub struct VestingClosed {
+    pub pool: Pubkey,
+    pub vesting: Pubkey,
+    pub owner: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct ClosePool<'info> {
-    #[account(mut)]
-    refundee: UncheckedAccount<'info>,
-    #[account(mut)]
-    staking_refundee: Box<Account<'info, TokenAccount>>,
-    #[account(mut)]
-    reward_refundee: Box<Account<'info, TokenAccount>>,
-    #[account(
-        mut,
-        close = refundee,
-        has_one = authority,
-        has_one = staking_vault,
-        has_one = reward_vault,
-        constraint = pool.paused,
-        constraint = pool.reward_duration_end > 0,
-        constraint = pool.reward_duration_end < sysvar::clock::Clock::get().unwrap().unix_timestamp.try_into().unwrap(),
-        constraint = pool.user_stake_count == 0,
-    )]
-    pool: Account<'info, Pool>,
-    authority: Signer<'info>,
-    #[account(mut,
-        constraint = staking_vault.amount == 0,
-    )]
-    staking_vault: Box<Account<'info, TokenAccount>>,
-    #[account(mut)]
-    reward_vault: Box<Account<'info, TokenAccount>>,
-    #[account(
-        seeds = [
-            pool.to_account_info().key.as_ref()
-        ],
-        bump = pool.nonce,
-    )]
-    pool_signer: UncheckedAccount<'info>,
-    token_program: Program<'info, Token>,
+#[event]
+pub struct RewardVested {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub owner: Pubkey,
+    pub stream_index: u8,
+    pub amount: u64,
+    pub cliff_time: u64,
+    pub end_time: u64,
+    pub timestamp: u64,
 }
 
-#[account]
-pub struct Pool {
-    /// Priviledged account.
-    pub authority: Pubkey,
-    /// Nonce to derive the program-derived address owning the vaults.
-    pub nonce: u8,
-    /// Paused state of the program
-    pub paused: bool,
-    /// Mint of the token that can be staked.
-    pub staking_mint: Pubkey,
-    /// Vault to store staked tokens.
-    pub staking_vault: Pubkey,
-    /// Mint of the reward A token.
-    pub reward_mint: Pubkey,
-    /// Vault to store reward A tokens.
-    pub reward_vault: Pubkey,
-    /// The period which rewards are linearly distributed.
-    pub reward_duration: u64,
-    /// The timestamp at which the current reward period ends.
-    pub reward_duration_end: u64,
-    /// Period to lock staked token and rewards
-    pub lock_period: u64,
-    /// The last time reward states were updated.
-    pub last_update_time: u64,
-    /// Rate of reward A distribution.
-    pub reward_rate: u64,
-    /// Last calculated reward A per pool token.
-    pub reward_per_token_stored: u128,
-    /// Users staked
-    pub user_stake_count: u32,
-    /// authorized funders
-    /// [] because short size, fixed account size, and ease of use on 
-    /// client due to auto generated account size property
-    pub funders: [Pubkey; 5],
+#[event]
+pub struct VestedWithdrawn {
+    pub pool: Pubkey,
+    pub vesting: Pubkey,
+    pub owner: Pubkey,
+    pub stream_index: u8,
+    pub amount: u64,
+    pub timestamp: u64,
 }
 
-#[account]
-#[derive(Default)]
-pub struct User {
-    /// Pool the this user belongs to.
+#[event]
+pub struct PendingRewards {
     pub pool: Pubkey,
-    /// The owner of this account.
+    pub user: Pubkey,
     pub owner: Pubkey,
-    /// The amount of token A claimed.
-    pub reward_per_token_complete: u128,
-    /// The amount of token A pending claim.
-    pub reward_per_token_pending: u64,
-    /// The amount staked.
+    /// Claimable rewards per stream.
+    pub pending: [u64; 4],
+    /// Per second reward rate per stream, scaled by `PRECISION`.
+    pub reward_rate: [u128; 4],
+    pub reward_duration_end: [u64; 4],
     pub balance_staked: u64,
-    /// Maturity time.
-    pub maturity_time: u64,
-    /// Signer nonce.
-    pub nonce: u8,
+    pub balance_weighted: u64,
+    pub total_staked: u64,
+    pub total_weighted_stake: u64,
+    /// The time from which rewards may be claimed.
+    pub claim_unlock_time: u64,
+    /// The earliest time any of the user's positions unlocks.
+    pub stake_unlock_time: u64,
+    pub timestamp: u64,
+}
+
+#[event]
+pub struct PoolClosed {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub refundee: Pubkey,
+    pub timestamp: u64,
 }
 
 #[error]
@@ -837,4 +3492,82 @@ pub enum ErrorCode {
     CannotDeauthorizeMissingAuthority,
     #[msg("Need to wait until maturity time to stake or claim.")]
     CannotStakeOrClaimBeforeMaturity,
+    #[msg("Staking vault holds no tokens beyond the staked total.")]
+    NoExcessStake,
+    #[msg("Reward stream already exists for this mint.")]
+    RewardStreamAlreadyExists,
+    #[msg("Maximum reward streams already added.")]
+    MaxRewardStreams,
+    #[msg("Reward vault does not match the reward stream.")]
+    InvalidRewardStream,
+    #[msg("Missing accounts for an active reward stream.")]
+    MissingRewardStreamAccounts,
+    #[msg("Maximum open stake positions reached.")]
+    MaxStakePositions,
+    #[msg("Early unstaking is disabled for this pool.")]
+    EarlyUnstakeDisabled,
+    #[msg("Invalid early unstake penalty configuration.")]
+    InvalidPenaltyConfig,
+    #[msg("Only rewards paid in the staking mint can be compounded.")]
+    CompoundRequiresStakingMint,
+    #[msg("Rewards in this pool vest, claim them into a vesting account.")]
+    ClaimMustVest,
+    #[msg("Reward vesting is disabled for this pool.")]
+    VestingDisabled,
+    #[msg("Vesting cliff cannot be longer than the vesting period.")]
+    InvalidVestingConfig,
+    #[msg("Maximum open vesting entries reached.")]
+    MaxVestingEntries,
+    #[msg("No vested rewards to withdraw.")]
+    NothingVested,
+    #[msg("Lock duration is outside the pool's allowed range.")]
+    InvalidLockDuration,
+    #[msg("Invalid lock duration configuration.")]
+    InvalidLockConfig,
+    #[msg("Reward schedule must start in the future and end after it starts.")]
+    InvalidRewardSchedule,
+    #[msg("Reward period is still running.")]
+    RewardPeriodActive,
+    #[msg("A scheduled reward period has not started yet.")]
+    RewardPeriodScheduled,
+    #[msg("Reward vault cannot cover owed rewards and the new reward rate.")]
+    InsufficientRewardFunds,
+    #[msg("New authority must differ from the current one.")]
+    InvalidNewAuthority,
+    #[msg("No authority transfer is pending.")]
+    NoPendingAuthority,
+    #[msg("Pause flags must be a non-empty set of known flags.")]
+    InvalidPauseFlags,
+    #[msg("Emergency withdrawals are not enabled.")]
+    EmergencyNotEnabled,
+    #[msg("Arithmetic overflow.")]
+    MathOverflow,
+    #[msg("Arithmetic underflow.")]
+    MathUnderflow,
+    #[msg("Clock timestamp is invalid.")]
+    InvalidTimestamp,
+    #[msg("Numeric conversion failed.")]
+    ConversionFailed,
+    #[msg("Division by zero.")]
+    DivisionByZero,
+    #[msg("Receipts can only be enabled once, before staking, in pools without lock periods.")]
+    InvalidReceiptConfig,
+    #[msg("Missing receipt accounts for a pool issuing receipt tokens.")]
+    MissingReceiptAccounts,
+    #[msg("Receipt account must be the owner's associated token account for the receipt mint.")]
+    InvalidReceiptAccount,
+    #[msg("Not supported by pools issuing receipt tokens.")]
+    ReceiptsUnsupported,
+}
+
+impl From<MathError> for ErrorCode {
+    fn from(err: MathError) -> Self {
+        match err {
+            MathError::Overflow => ErrorCode::MathOverflow,
+            MathError::Underflow => ErrorCode::MathUnderflow,
+            MathError::DivisionByZero => ErrorCode::DivisionByZero,
+            MathError::InvalidTimestamp => ErrorCode::InvalidTimestamp,
+            MathError::Conversion => ErrorCode::ConversionFailed,
+        }
+    }
 }
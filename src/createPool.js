@@ -6,6 +6,7 @@ const createPool = async(lockPeriod, amount) => {
   const {
     rewardPoolProgram,
     cysMint,
+    cysTokenAccount,
     pools,
     wallet
   } = getAccounts();
@@ -35,6 +36,18 @@ const createPool = async(lockPeriod, amount) => {
     nonce,
     rewardDuration,
     lockPeriod,
+    {
+      maxLockPeriod: lockPeriod,
+      maxLockMultiplierBps: new anchor.BN(10000),
+      penaltyCurve: { disabled: {} },
+      earlyUnstakePenaltyBps: 0,
+      penaltyDestination: { treasury: {} },
+      rewardLockPolicy: { withPrincipal: {} },
+      rewardLockPeriod: new anchor.BN(0),
+      compoundResetsLock: true,
+      vestingPeriod: new anchor.BN(0),
+      vestingCliff: new anchor.BN(0),
+    },
     {
       accounts: {
         authority: wallet.publicKey,
@@ -42,6 +55,7 @@ const createPool = async(lockPeriod, amount) => {
         stakingVault,
         rewardMint: cysMint.publicKey,
         rewardVault,
+        treasury: cysTokenAccount,
         poolSigner: poolSigner,
         pool: pool.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID
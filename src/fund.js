@@ -13,6 +13,7 @@ const fund = async(lockPeriod, amount) => {
   const pool = pools.find(_pool => _pool.lockPeriod.toString() === lockPeriod.toString())
 
   const tx = await rewardPoolProgram.rpc.fund(
+    0,
     amount,
     {
       accounts: {
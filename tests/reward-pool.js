@@ -12,6 +12,7 @@ describe('reward-pool', () => {
   let cyclosMint;
   let stakingVault;
   let rewardVault;
+  let treasury;
   let pool;
   let poolSigner;
   let nonce;
@@ -42,6 +43,9 @@ describe('reward-pool', () => {
     rewardVault = await cyclosMint.createAccount(poolSigner)
     console.log("rewardVault: ", rewardVault.toString())
 
+    treasury = await cyclosMint.createAccount(wallet.publicKey)
+    console.log("Treasury: ", treasury.toString())
+
     console.log("Pool Signer: ", poolSigner.toString());
     console.log("Pool Nonce: ", nonce.toString());
 
@@ -49,6 +53,18 @@ describe('reward-pool', () => {
       nonce,
       rewardDuration,
       lockPeriod,
+      {
+        maxLockPeriod: lockPeriod,
+        maxLockMultiplierBps: new anchor.BN(10000),
+        penaltyCurve: { disabled: {} },
+        earlyUnstakePenaltyBps: 0,
+        penaltyDestination: { treasury: {} },
+        rewardLockPolicy: { withPrincipal: {} },
+        rewardLockPeriod: new anchor.BN(0),
+        compoundResetsLock: true,
+        vestingPeriod: new anchor.BN(0),
+        vestingCliff: new anchor.BN(0),
+      },
       {
         accounts: {
           authority: wallet.publicKey,
@@ -56,6 +72,7 @@ describe('reward-pool', () => {
           stakingVault,
           rewardMint: cyclosMint.publicKey,
           rewardVault,
+          treasury,
           poolSigner: poolSigner,
           pool: pool.publicKey,
           tokenProgram: TOKEN_PROGRAM_ID
@@ -72,20 +89,41 @@ describe('reward-pool', () => {
     it('check initialized pool values', async () => {
       const poolAccount = await rewardPool.account.pool.fetch(pool.publicKey);
       assert.equal(poolAccount.authority.toString(), wallet.publicKey.toString());
+      assert.equal(poolAccount.pendingAuthority.toString(), new PublicKey(0).toString());
       assert.equal(poolAccount.nonce, nonce);
-      assert.equal(poolAccount.paused, false);
+      assert.equal(poolAccount.pauseFlags, 0);
+      assert.equal(poolAccount.guardian.toString(), new PublicKey(0).toString());
+      assert.equal(poolAccount.emergency, false);
       assert.equal(poolAccount.stakingMint.toString(), cyclosMint.publicKey);
       assert.equal(poolAccount.stakingVault.toString(), stakingVault);
-      assert.equal(poolAccount.rewardMint.toString(), cyclosMint.publicKey);
-      assert.equal(poolAccount.rewardVault.toString(), rewardVault);
+      assert.equal(poolAccount.rewardStreams.length, 4);
+      assert.equal(poolAccount.rewardStreams[0].mint.toString(), cyclosMint.publicKey);
+      assert.equal(poolAccount.rewardStreams[0].vault.toString(), rewardVault);
       assert.equal(poolAccount.rewardDuration.toString(), rewardDuration.toString());
-      assert.equal(poolAccount.rewardDurationEnd.toString(), '0');
+      assert.equal(poolAccount.rewardStreams[0].rewardStartTime.toString(), '0');
+      assert.equal(poolAccount.rewardStreams[0].rewardDurationEnd.toString(), '0');
       assert.equal(poolAccount.lockPeriod.toString(), lockPeriod.toString());
-      assert.equal(poolAccount.lastUpdateTime.toString(), '0');
-      assert.equal(poolAccount.rewardRate.toString(), '0');
-      assert.equal(poolAccount.rewardPerTokenStored.toString(), '0');
+      assert.equal(poolAccount.rewardStreams[0].lastUpdateTime.toString(), '0');
+      assert.equal(poolAccount.rewardStreams[0].rewardRate.toString(), '0');
+      assert.equal(poolAccount.rewardStreams[0].rewardPerTokenStored.toString(), '0');
+      assert.equal(poolAccount.rewardStreams[0].undistributed.toString(), '0');
+      assert.equal(poolAccount.rewardStreams[0].rewardLiability.toString(), '0');
       assert.equal(poolAccount.userStakeCount.toString(), '0');
+      assert.equal(poolAccount.totalStaked.toString(), '0');
+      assert.equal(poolAccount.totalWeightedStake.toString(), '0');
+      assert.equal(poolAccount.maxLockPeriod.toString(), lockPeriod.toString());
+      assert.equal(poolAccount.maxLockMultiplierBps.toString(), '10000');
       assert.equal(poolAccount.funders.length, 5);
+      assert.deepEqual(poolAccount.penaltyCurve, { disabled: {} });
+      assert.equal(poolAccount.earlyUnstakePenaltyBps, 0);
+      assert.deepEqual(poolAccount.penaltyDestination, { treasury: {} });
+      assert.equal(poolAccount.treasury.toString(), treasury.toString());
+      assert.deepEqual(poolAccount.rewardLockPolicy, { withPrincipal: {} });
+      assert.equal(poolAccount.rewardLockPeriod.toString(), '0');
+      assert.equal(poolAccount.compoundResetsLock, true);
+      assert.equal(poolAccount.vestingPeriod.toString(), '0');
+      assert.equal(poolAccount.vestingCliff.toString(), '0');
+      assert.equal(poolAccount.receiptMint.toString(), new PublicKey(0).toString());
     });
   })
 
@@ -94,6 +132,7 @@ describe('reward-pool', () => {
       const fundAmount = new anchor.BN("10000000000");
 
       await rewardPool.rpc.fund(
+        0,
         fundAmount,
         {
           accounts: {